@@ -0,0 +1,74 @@
+#!/usr/bin/env python3
+import sys
+
+try:
+    import gi
+    gi.require_version('Gtk', '3.0')
+    from gi.repository import Gtk
+except ImportError:
+    print("GTK3 not available")
+    sys.exit(1)
+
+class ConfirmClearDialog(Gtk.Dialog):
+    def __init__(self, include_pinned):
+        Gtk.Dialog.__init__(self, title="Clear History")
+        self.set_default_size(400, 150)
+        self.set_border_width(10)
+
+        # Content area
+        content = self.get_content_area()
+        content.set_spacing(10)
+
+        # Message
+        if include_pinned:
+            detail = "All history, including pinned items, will be removed from every device."
+        else:
+            detail = "History will be removed from every device. Pinned items are kept."
+        message_label = Gtk.Label()
+        message_label.set_markup(f"<b>Clear clipboard history?</b>\n\n{detail}")
+        message_label.set_line_wrap(True)
+        message_label.set_halign(Gtk.Align.CENTER)
+        content.pack_start(message_label, True, True, 10)
+
+        # Button box
+        button_box = self.get_action_area()
+
+        # Cancel button
+        cancel_btn = Gtk.Button(label="Cancel")
+        cancel_btn.connect("clicked", self.on_cancel)
+        button_box.pack_start(cancel_btn, False, False, 0)
+
+        # Clear button
+        clear_btn = Gtk.Button(label="Clear History")
+        clear_btn.connect("clicked", self.on_clear)
+
+        # Style clear button
+        clear_css = Gtk.CssProvider()
+        clear_css.load_from_data(b"""
+            button {
+                background-color: #e74c3c;
+                color: white;
+            }
+        """)
+        clear_btn.get_style_context().add_provider(clear_css, Gtk.STYLE_PROVIDER_PRIORITY_APPLICATION)
+
+        button_box.pack_end(clear_btn, False, False, 0)
+
+        self.result = "cancel"
+
+    def on_cancel(self, button):
+        self.result = "cancel"
+        Gtk.main_quit()
+
+    def on_clear(self, button):
+        self.result = "clear"
+        Gtk.main_quit()
+
+if __name__ == "__main__":
+    dialog = ConfirmClearDialog("--pinned" in sys.argv[1:])
+    dialog.connect("destroy", Gtk.main_quit)
+    dialog.show_all()
+    Gtk.main()
+
+    # Print result for Rust to read
+    print(dialog.result)
@@ -0,0 +1,157 @@
+#!/usr/bin/env python3
+import sys
+import json
+import subprocess
+from datetime import datetime
+
+try:
+    import gi
+    gi.require_version('Gtk', '3.0')
+    from gi.repository import Gtk, GLib
+except ImportError:
+    print("GTK3 not available, install python3-gi")
+    sys.exit(1)
+
+# Searches run through `corridor history search`, so the ranking matches the command line
+MODES = [("Text", None), ("Fuzzy", "--fuzzy"), ("Regex", "--regex")]
+
+class SearchDialog(Gtk.Window):
+    def __init__(self, corridor_path):
+        Gtk.Window.__init__(self, title="Search History")
+        self.set_default_size(700, 500)
+        self.set_border_width(10)
+        self.corridor_path = corridor_path
+        self.pending_search = None
+
+        main_box = Gtk.Box(orientation=Gtk.Orientation.VERTICAL, spacing=8)
+        self.add(main_box)
+
+        # Search row: query + mode
+        search_row = Gtk.Box(orientation=Gtk.Orientation.HORIZONTAL, spacing=8)
+
+        self.entry = Gtk.SearchEntry()
+        self.entry.set_placeholder_text("Search clipboard history")
+        self.entry.connect("search-changed", self.on_query_changed)
+        self.entry.connect("activate", self.on_entry_activate)
+        search_row.pack_start(self.entry, True, True, 0)
+
+        self.mode_combo = Gtk.ComboBoxText()
+        for label, _ in MODES:
+            self.mode_combo.append_text(label)
+        self.mode_combo.set_active(0)
+        self.mode_combo.connect("changed", self.on_query_changed)
+        search_row.pack_start(self.mode_combo, False, False, 0)
+
+        main_box.pack_start(search_row, False, False, 0)
+
+        self.status_label = Gtk.Label()
+        self.status_label.set_halign(Gtk.Align.START)
+        main_box.pack_start(self.status_label, False, False, 0)
+
+        # Results
+        scrolled = Gtk.ScrolledWindow()
+        scrolled.set_policy(Gtk.PolicyType.NEVER, Gtk.PolicyType.AUTOMATIC)
+        self.results = Gtk.ListBox()
+        self.results.set_selection_mode(Gtk.SelectionMode.SINGLE)
+        self.results.connect("row-activated", self.on_row_activated)
+        scrolled.add(self.results)
+        main_box.pack_start(scrolled, True, True, 0)
+
+        hint = Gtk.Label()
+        hint.set_markup("<span foreground='gray'>Double-click or press Enter to copy</span>")
+        hint.set_halign(Gtk.Align.START)
+        main_box.pack_start(hint, False, False, 0)
+
+    def on_query_changed(self, widget):
+        # Debounce so typing doesn't start a search per keystroke
+        if self.pending_search is not None:
+            GLib.source_remove(self.pending_search)
+        self.pending_search = GLib.timeout_add(200, self.run_search)
+
+    def run_search(self):
+        self.pending_search = None
+        query = self.entry.get_text().strip()
+
+        for row in self.results.get_children():
+            self.results.remove(row)
+
+        if not query:
+            self.status_label.set_text("")
+            return False
+
+        command = [self.corridor_path, "history", "search", "--json"]
+        mode_flag = MODES[self.mode_combo.get_active()][1]
+        if mode_flag:
+            command.append(mode_flag)
+        command.append(query)
+
+        try:
+            result = subprocess.run(command, capture_output=True, text=True, timeout=5)
+        except Exception as e:
+            self.status_label.set_text(f"Search failed: {e}")
+            return False
+
+        if result.returncode != 0:
+            self.status_label.set_text(result.stderr.strip().lstrip("❌ "))
+            return False
+
+        items = json.loads(result.stdout or "[]")
+        self.status_label.set_text(f"{len(items)} matching item(s)" if items else "No matches")
+        for item in items:
+            self.add_result(item)
+        self.results.show_all()
+        return False
+
+    def add_result(self, item):
+        row = Gtk.ListBoxRow()
+        row.item_id = item['id']
+
+        box = Gtk.Box(orientation=Gtk.Orientation.VERTICAL, spacing=2)
+        box.set_margin_top(4)
+        box.set_margin_bottom(4)
+        box.set_margin_start(6)
+        box.set_margin_end(6)
+
+        ts = datetime.fromtimestamp(item['timestamp'] / 1000)
+        meta = ts.strftime("%m/%d/%Y, %I:%M %p")
+        if item.get('device_name'):
+            meta += f"  ·  from {item['device_name']}"
+        meta_label = Gtk.Label()
+        meta_label.set_markup(f"<span foreground='gray' size='small'>{GLib.markup_escape_text(meta)}</span>")
+        meta_label.set_halign(Gtk.Align.START)
+        box.pack_start(meta_label, False, False, 0)
+
+        preview = item['content'].strip().replace("\n", " ⏎ ")
+        if len(preview) > 200:
+            preview = preview[:200] + "..."
+        content_label = Gtk.Label(label=preview)
+        content_label.set_halign(Gtk.Align.START)
+        content_label.set_line_wrap(True)
+        content_label.set_xalign(0)
+        box.pack_start(content_label, False, False, 0)
+
+        row.add(box)
+        self.results.add(row)
+
+    def on_entry_activate(self, entry):
+        row = self.results.get_selected_row() or self.results.get_row_at_index(0)
+        if row is not None:
+            self.on_row_activated(self.results, row)
+
+    def on_row_activated(self, listbox, row):
+        # The tray copies the item, so it goes through the app's own clipboard handling
+        print(f"COPY:{row.item_id}", flush=True)
+        self.destroy()
+
+def main():
+    if len(sys.argv) < 2:
+        print("Usage: search_history.py <corridor-path>")
+        sys.exit(1)
+
+    win = SearchDialog(sys.argv[1])
+    win.connect("destroy", Gtk.main_quit)
+    win.show_all()
+    Gtk.main()
+
+if __name__ == "__main__":
+    main()
@@ -247,19 +247,19 @@ class SettingsDialog(Gtk.Window):
             if self.silent_radio.get_active():
                 self.config["mode"] = "silent"
                 # Disable notifications in silent mode
-                self.config["notifications"] = {
+                self.config.setdefault("notifications", {}).update({
                     "local_copy": False,
                     "remote_update": False,
                     "errors": False
-                }
+                })
             else:
                 self.config["mode"] = "interactive"
                 # Enable notifications in interactive mode
-                self.config["notifications"] = {
+                self.config.setdefault("notifications", {}).update({
                     "local_copy": True,
                     "remote_update": True,
                     "errors": True
-                }
+                })
 
             # Ensure other config fields exist with defaults
             if "clipboard" not in self.config:
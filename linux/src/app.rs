@@ -0,0 +1,861 @@
+use crate::clipboard::{ClipboardEvent, ClipboardManager};
+use crate::config::{Config, SelectionTarget};
+use crate::history::{ClipboardHistory, HistoryItem};
+use crate::notifications::{NotificationAction, Notifier};
+use crate::rooms::{local_room, Connections, Presence, RoomRouter};
+use crate::transfer::format_size;
+use crate::tray::{self, TrayIcon};
+use crate::websocket::{DeviceInfo, HistoryData, OutgoingMessage, WsEvent};
+use anyhow::Result;
+use notify_rust::Timeout;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+// How long the Undo / Copy again actions stay available after a remote update
+const UNDO_NOTIFICATION_MS: u32 = 8000;
+// How long an Apply / Dismiss notification waits for a choice; the tray still offers it after
+const APPLY_NOTIFICATION_MS: u32 = 60_000;
+
+// How often history is checked for items past the configured max age
+const RETENTION_CHECK_SECS: u64 = 60;
+
+// A sensitive remote item whose auto-clear timer has fired
+struct SensitiveExpiry {
+    id: String,
+    content: String,
+    previous: Option<String>,
+    // The selection the item was written to, which is the one to check and clear
+    target: SelectionTarget,
+    // Queued in manual-accept mode rather than written to the clipboard
+    pending: bool,
+}
+
+/// What the event loop works with, built by `main` from the config and by tests around
+/// an in-memory clipboard
+pub struct App {
+    pub config: Config,
+    pub history: Arc<Mutex<ClipboardHistory>>,
+    pub clipboard: Arc<ClipboardManager>,
+    pub rooms: Arc<RoomRouter>,
+    pub presence: Arc<Mutex<Presence>>,
+    pub connections: Arc<Mutex<Connections>>,
+    pub notifier: Notifier,
+    /// The tray's connection indicator and handle, in interactive mode
+    pub tray: Option<(Arc<Mutex<bool>>, ksni::Handle<TrayIcon>)>,
+}
+
+/// The events the loop reacts to
+pub struct Channels {
+    pub clipboard: mpsc::UnboundedReceiver<ClipboardEvent>,
+    /// Events from every room's client, tagged with the room name
+    pub ws: mpsc::UnboundedReceiver<(String, WsEvent)>,
+    pub actions: mpsc::UnboundedReceiver<NotificationAction>,
+}
+
+/// Handles local clipboard changes, room events and notification actions for as long as
+/// the app runs
+pub async fn run(app: App, channels: Channels) {
+    let App {
+        config,
+        history,
+        clipboard: clipboard_manager,
+        rooms,
+        presence,
+        connections,
+        notifier,
+        tray,
+    } = app;
+    let Channels {
+        clipboard: mut clipboard_rx,
+        ws: mut ws_rx,
+        actions: mut action_rx,
+    } = channels;
+    let (tray_connected, tray_handle) = match tray {
+        Some((connected, handle)) => (Some(connected), Some(handle)),
+        None => (None, None),
+    };
+
+    // Rooms whose server will echo back a clear we asked for
+    let mut own_clears: HashSet<String> = HashSet::new();
+    let (sensitive_tx, mut sensitive_rx) = mpsc::unbounded_channel::<SensitiveExpiry>();
+
+    let history_for_clipboard = history.clone();
+    let notify_enabled = config.notifications.remote_update;
+    let history_for_sync = history.clone();
+
+    // Items age out of history even when nothing new arrives
+    let mut retention_interval = tokio::time::interval(std::time::Duration::from_secs(RETENTION_CHECK_SECS));
+
+    loop {
+        tokio::select! {
+            Some(event) = clipboard_rx.recv() => {
+                match event {
+                    ClipboardEvent::LocalChange(_) if !config.device.direction.sends() => {
+                        log::debug!("Receive-only device, not sharing local clipboard change");
+                    }
+                    ClipboardEvent::LocalChange(content) => {
+                        log::info!("Local clipboard changed");
+
+                        // Check if this is different from the last item to avoid duplicates
+                        let should_send = {
+                            let hist = history.lock().unwrap();
+                            let recent = hist.get_recent(1);
+                            recent.is_empty() || recent[0].content != content
+                        };
+
+                        if should_send {
+                            // Try to send to the routed rooms, if that fails add to queue
+                            let sent_to = rooms.broadcast(&content);
+                            history.lock().unwrap().add_local(content.clone(), local_room(&sent_to));
+                            if sent_to.is_empty() {
+                                log::warn!("WebSocket not available, adding to sync queue");
+                                history.lock().unwrap().add_to_sync_queue(content.clone());
+                            }
+
+                            // Always trigger immediate tray update when history changes
+                            if let Some(ref handle) = tray_handle {
+                                handle.update(|tray| {
+                                    if let Ok(mut counter) = tray.refresh_counter.lock() {
+                                        *counter = counter.wrapping_add(1);
+                                    }
+                                });
+                            }
+
+                            if config.notifications.local_copy {
+                                notifier.notify("Copied", &content.chars().take(50).collect::<String>());
+                            }
+                        } else {
+                            log::debug!("Skipping duplicate local clipboard update");
+                        }
+                    }
+                    ClipboardEvent::Error(err) => {
+                        log::error!("Clipboard error: {}", err);
+                        if config.notifications.errors {
+                            notifier.notify("Clipboard Error", &err);
+                        }
+                    }
+                }
+            }
+
+            Some((room, event)) = ws_rx.recv() => {
+                match event {
+                    WsEvent::Connected => {
+                        log::info!("✓ WebSocket connected to room {}", room);
+                        connections.lock().unwrap().insert(room.clone(), None);
+                        if let Some(ref tray_conn) = tray_connected {
+                            *tray_conn.lock().unwrap() = true;
+                        }
+
+                        // Trigger immediate tray update
+                        if let Some(ref handle) = tray_handle {
+                            handle.update(|tray| {
+                                if let Ok(mut counter) = tray.refresh_counter.lock() {
+                                    *counter = counter.wrapping_add(1);
+                                }
+                            });
+                        }
+
+                        // Sync pending items from queue
+                        let pending_items = {
+                            let mut hist = history_for_sync.lock().unwrap();
+                            let count = hist.pending_sync_count();
+                            if count > 0 {
+                                log::info!("Found {} pending items to sync", count);
+                                hist.get_pending_syncs()
+                            } else {
+                                Vec::new()
+                            }
+                        };
+
+                        if !pending_items.is_empty() {
+                            let count = pending_items.len();
+                            log::info!("Syncing {} queued items to server", count);
+                            for pending in pending_items {
+                                if rooms.broadcast(&pending.content).is_empty() {
+                                    log::error!("Failed to sync queued item");
+                                    // Re-add to queue if failed
+                                    history_for_sync.lock().unwrap().add_to_sync_queue(pending.content);
+                                } else {
+                                    log::info!("✓ Synced queued item");
+                                }
+                            }
+                            notifier.notify("Corridor", &format!("Synced {} queued clipboard items", count));
+
+                            // Trigger immediate tray update to show pending count cleared
+                            if let Some(ref handle) = tray_handle {
+                                handle.update(|tray| {
+                                    if let Ok(mut counter) = tray.refresh_counter.lock() {
+                                        *counter = counter.wrapping_add(1);
+                                    }
+                                });
+                            }
+                        }
+
+                        if config.notifications.remote_update {
+                            notifier.notify("Corridor", &connection_message("Connected to sync server", &room, &rooms));
+                        }
+                    }
+                    WsEvent::Disconnected => {
+                        log::warn!("✗ WebSocket disconnected from room {}", room);
+                        let any_connected = {
+                            let mut connections = connections.lock().unwrap();
+                            connections.remove(&room);
+                            !connections.is_empty()
+                        };
+                        if let Some(ref tray_conn) = tray_connected {
+                            *tray_conn.lock().unwrap() = any_connected;
+                        }
+                        presence.lock().unwrap().remove(&room);
+                        // A clear sent on this connection won't be echoed back, and one the
+                        // server processes after reconnecting must not be mistaken for ours
+                        own_clears.remove(&room);
+
+                        // Trigger immediate tray update
+                        if let Some(ref handle) = tray_handle {
+                            handle.update(|tray| {
+                                if let Ok(mut counter) = tray.refresh_counter.lock() {
+                                    *counter = counter.wrapping_add(1);
+                                }
+                            });
+                        }
+
+                        if config.notifications.errors {
+                            notifier.notify("Corridor", &connection_message("Disconnected from sync server", &room, &rooms));
+                        }
+                    }
+                    WsEvent::ClipboardUpdate(_) if !config.device.direction.receives() => {
+                        log::debug!("Send-only device, ignoring remote clipboard update");
+                    }
+                    WsEvent::ClipboardUpdate(update) => {
+                        log::info!("Remote clipboard update received");
+                        let content = update.content.clone();
+
+                        // Check if this is different from current content to avoid duplicates
+                        let should_update = {
+                            let hist = history_for_clipboard.lock().unwrap();
+                            let recent = hist.get_recent(1);
+                            recent.is_empty() || recent[0].content != content
+                        };
+
+                        let mut item = update.to_history_item();
+                        item.room = Some(room.clone());
+                        // Bursts from one device are announced together
+                        let source = item.device_name.clone().unwrap_or_else(|| "another device".to_string());
+
+                        if should_update && config.clipboard.manual_accept {
+                            // Leave the clipboard alone until the user accepts the item
+                            item.pending = true;
+                            let id = item.id.clone();
+                            if item.sensitive {
+                                schedule_expiry(&item, None, clipboard_manager.remote_target(), true, config.clipboard.sensitive_clear_secs, &sensitive_tx);
+                            }
+                            history_for_clipboard.lock().unwrap().add(item);
+                            log::info!("Remote item {} is waiting to be applied", id);
+
+                            if let Some(ref handle) = tray_handle {
+                                handle.update(|tray| {
+                                    if let Ok(mut counter) = tray.refresh_counter.lock() {
+                                        *counter = counter.wrapping_add(1);
+                                    }
+                                });
+                            }
+
+                            if notify_enabled {
+                                let body = if update.sensitive {
+                                    "Sensitive item received".to_string()
+                                } else {
+                                    content.chars().take(50).collect::<String>()
+                                };
+                                notifier.notify_item(
+                                    "Remote Clipboard",
+                                    &source,
+                                    &body,
+                                    Timeout::Milliseconds(APPLY_NOTIFICATION_MS),
+                                    vec![
+                                        ("Apply", NotificationAction::Apply(id.clone())),
+                                        ("Dismiss", NotificationAction::Dismiss(id)),
+                                    ],
+                                );
+                            }
+                        } else if should_update {
+                            let origin = update.origin.clone().unwrap_or_else(|| update.id.clone());
+                            let id = item.id.clone();
+                            match apply_remote(&clipboard_manager, &item, origin, config.clipboard.sensitive_clear_secs, &sensitive_tx) {
+                                Err(e) => log::error!("Failed to update local clipboard: {}", e),
+                                Ok((previous, target)) => {
+                                    history_for_clipboard.lock().unwrap().add(item);
+                                    log::info!("✓ Updated local clipboard from remote");
+
+                                    // Trigger immediate tray update to show new history item
+                                    if let Some(ref handle) = tray_handle {
+                                        handle.update(|tray| {
+                                            if let Ok(mut counter) = tray.refresh_counter.lock() {
+                                                *counter = counter.wrapping_add(1);
+                                            }
+                                        });
+                                    }
+
+                                    if notify_enabled {
+                                        let body = if update.sensitive {
+                                            "Sensitive item received".to_string()
+                                        } else {
+                                            content.chars().take(50).collect::<String>()
+                                        };
+                                        let mut actions = Vec::new();
+                                        if let Some(previous) = previous {
+                                            actions.push(("Undo", NotificationAction::Undo { id: id.clone(), previous, target }));
+                                        }
+                                        actions.push(("Copy again", NotificationAction::CopyAgain(id)));
+                                        actions.push(("Open history", NotificationAction::OpenHistory(room.clone())));
+                                        notifier.notify_item(
+                                            "Remote Clipboard",
+                                            &source,
+                                            &body,
+                                            Timeout::Milliseconds(UNDO_NOTIFICATION_MS),
+                                            actions,
+                                        );
+                                    }
+                                }
+                            }
+                        } else {
+                            log::debug!("Skipping duplicate clipboard update");
+                        }
+                    }
+                    WsEvent::ClipboardHistory(ref items) | WsEvent::HistoryResync(ref items) => {
+                        log::info!("Received {} history items from room {}", items.len(), room);
+                        let items = items
+                            .iter()
+                            .map(|data| {
+                                let mut item = data.to_history_item();
+                                item.room = Some(room.clone());
+                                item
+                            })
+                            .collect();
+                        if matches!(event, WsEvent::HistoryResync(_)) {
+                            history.lock().unwrap().resync_room(&room, items);
+                        } else {
+                            // Merge rather than replace so items the server never got are kept
+                            history.lock().unwrap().merge(items);
+                        }
+                        log::info!("✓ Local history synced with server");
+
+                        // Trigger immediate tray update to show new history
+                        if let Some(ref handle) = tray_handle {
+                            handle.update(|tray| {
+                                if let Ok(mut counter) = tray.refresh_counter.lock() {
+                                    *counter = counter.wrapping_add(1);
+                                }
+                            });
+                        }
+                    }
+                    WsEvent::ClearHistory { .. } if own_clears.remove(&room) => {
+                        // Already cleared locally, and an undo may have restored history since
+                        log::info!("Room {} confirmed our history clear", room);
+                    }
+                    WsEvent::ClearHistory { include_pinned } => {
+                        log::info!("Clearing local history of room {} (server cleared)", room);
+                        history.lock().unwrap().clear_room(&room, include_pinned);
+                        log::info!("✓ Local history cleared");
+
+                        // Trigger immediate tray update to show cleared history
+                        if let Some(ref handle) = tray_handle {
+                            handle.update(|tray| {
+                                if let Ok(mut counter) = tray.refresh_counter.lock() {
+                                    *counter = counter.wrapping_add(1);
+                                }
+                            });
+                        }
+
+                        if config.notifications.remote_update {
+                            notifier.notify("Corridor", &connection_message("History cleared", &room, &rooms));
+                        }
+                    }
+                    WsEvent::Pin { id, content, pinned } => {
+                        if history.lock().unwrap().set_pinned(&id, &content, pinned).is_some() {
+                            if let Some(ref handle) = tray_handle {
+                                handle.update(|tray| {
+                                    if let Ok(mut counter) = tray.refresh_counter.lock() {
+                                        *counter = counter.wrapping_add(1);
+                                    }
+                                });
+                            }
+                        }
+                    }
+                    WsEvent::DeleteItem { id, content } => {
+                        if history.lock().unwrap().remove_matching(&id, &content).is_some() {
+                            log::info!("✓ Removed item deleted on another device");
+                            if let Some(ref handle) = tray_handle {
+                                handle.update(|tray| {
+                                    if let Ok(mut counter) = tray.refresh_counter.lock() {
+                                        *counter = counter.wrapping_add(1);
+                                    }
+                                });
+                            }
+                        }
+                    }
+                    WsEvent::Presence(devices) => {
+                        let others: Vec<DeviceInfo> = devices
+                            .into_iter()
+                            .filter(|device| device.id != config.device.id)
+                            .collect();
+                        log::info!("{} other device(s) online in room {}", others.len(), room);
+
+                        presence.lock().unwrap().insert(room, others);
+
+                        if let Some(ref handle) = tray_handle {
+                            handle.update(|tray| {
+                                if let Ok(mut counter) = tray.refresh_counter.lock() {
+                                    *counter = counter.wrapping_add(1);
+                                }
+                            });
+                        }
+                    }
+                    WsEvent::Latency(latency) => {
+                        // A late pong can race the disconnect, which already removed the room
+                        if let Some(entry) = connections.lock().unwrap().get_mut(&room) {
+                            *entry = Some(latency);
+                        }
+                    }
+                    WsEvent::TooLarge { size, limit, incoming } => {
+                        let message = if incoming {
+                            format!("Skipped an incoming item of {}, over the {} limit", format_size(size), format_size(limit))
+                        } else {
+                            format!("Not synced: the item is {}, over the {} limit", format_size(size), format_size(limit))
+                        };
+                        if config.notifications.errors {
+                            notifier.notify("Item Too Large", &connection_message(&message, &room, &rooms));
+                        }
+                    }
+                    WsEvent::Error(err) => {
+                        log::error!("WebSocket error in room {}: {}", room, err);
+                        if config.notifications.errors {
+                            notifier.notify("Connection Error", &err);
+                        }
+                    }
+                }
+            }
+
+            Some(action) = action_rx.recv() => {
+                match action {
+                    NotificationAction::Apply(id) => {
+                        let item = {
+                            let mut hist = history.lock().unwrap();
+                            hist.clear_pending(&id);
+                            hist.get(&id).cloned()
+                        };
+                        match item {
+                            Some(item) => {
+                                let origin = item.id.clone();
+                                match apply_remote(&clipboard_manager, &item, origin, config.clipboard.sensitive_clear_secs, &sensitive_tx) {
+                                    Ok(_) => log::info!("✓ Applied remote item {}", id),
+                                    Err(e) => log::error!("Failed to apply remote item: {}", e),
+                                }
+                            }
+                            None => log::warn!("Remote item {} is no longer in history", id),
+                        }
+                    }
+                    NotificationAction::Dismiss(id) => {
+                        let mut hist = history.lock().unwrap();
+                        if hist.clear_pending(&id) {
+                            log::info!("Dismissed remote item {}", id);
+                            // A dismissed sensitive item will never be applied, so don't keep it
+                            if hist.get(&id).is_some_and(|item| item.sensitive) {
+                                hist.remove(&id);
+                            }
+                        }
+                    }
+                    NotificationAction::Undo { id, previous, target } => {
+                        // Only roll back if the remote item is still what its selection holds
+                        let replaced = history.lock().unwrap().get(&id).map(|item| item.content.clone());
+                        let still_current = match replaced {
+                            Some(content) => clipboard_manager.get_text_in(target).is_ok_and(|text| text == content),
+                            None => true,
+                        };
+
+                        if still_current {
+                            let result = if previous.is_empty() {
+                                clipboard_manager.clear_in(target)
+                            } else {
+                                clipboard_manager.set_text_in(target, previous, Some(format!("undo of {}", id)))
+                            };
+                            match result {
+                                Ok(()) => log::info!("✓ Restored clipboard replaced by {}", id),
+                                Err(e) => log::error!("Failed to restore clipboard: {}", e),
+                            }
+                        } else {
+                            log::info!("Clipboard changed since {} was applied, nothing to undo", id);
+                        }
+                    }
+                    NotificationAction::CopyAgain(id) => {
+                        let item = history.lock().unwrap().get(&id).cloned();
+                        match item {
+                            Some(item) => {
+                                let origin = format!("copy again of {}", id);
+                                if let Err(e) = apply_remote(&clipboard_manager, &item, origin, config.clipboard.sensitive_clear_secs, &sensitive_tx) {
+                                    log::error!("Failed to copy item again: {}", e);
+                                }
+                            }
+                            None => log::warn!("Item {} is no longer in history", id),
+                        }
+                    }
+                    NotificationAction::OpenHistory(room) => {
+                        match rooms.room(&room) {
+                            Some(room) => tray::show_history(room),
+                            None => log::warn!("Unknown room {}", room),
+                        }
+                    }
+                    NotificationAction::ClearHistory { include_pinned } => {
+                        let undo_secs = config.clipboard.clear_undo_secs;
+                        history
+                            .lock()
+                            .unwrap()
+                            .clear(include_pinned, std::time::Duration::from_secs(undo_secs));
+
+                        // Over the WebSocket rather than the HTTP API so a quick undo can't overtake the clear
+                        for room in rooms.rooms() {
+                            if rooms.send(&room.name, OutgoingMessage::ClearHistory { include_pinned }) {
+                                own_clears.insert(room.name.clone());
+                            }
+                        }
+                        log::info!("✓ History cleared");
+
+                        let body = if include_pinned { "History cleared, including pinned items" } else { "History cleared" };
+                        if undo_secs == 0 {
+                            notifier.notify("Corridor", body);
+                        } else {
+                            notifier.notify_item(
+                                "Corridor",
+                                "clear",
+                                body,
+                                Timeout::Milliseconds(u32::try_from(undo_secs.saturating_mul(1000)).unwrap_or(u32::MAX)),
+                                vec![("Undo", NotificationAction::UndoClear)],
+                            );
+                        }
+                    }
+                    NotificationAction::UndoClear => {
+                        let restored = history.lock().unwrap().undo_clear();
+                        match restored {
+                            Some(items) => {
+                                // Re-upload each room's items; local items shared with several rooms go to all of them
+                                for room in rooms.rooms() {
+                                    let room_items: Vec<HistoryData> = items
+                                        .iter()
+                                        .filter(|item| !item.sensitive)
+                                        .filter(|item| item.room.as_ref().is_none_or(|name| *name == room.name))
+                                        .map(HistoryData::from_history_item)
+                                        .collect();
+                                    if !room_items.is_empty() {
+                                        rooms.send(&room.name, OutgoingMessage::RestoreHistory(room_items));
+                                    }
+                                }
+                                log::info!("✓ Restored {} cleared history items", items.len());
+                                notifier.notify("Corridor", "History restored");
+                            }
+                            None => log::info!("Too late to undo the last history clear"),
+                        }
+                    }
+                }
+
+                if let Some(ref handle) = tray_handle {
+                    handle.update(|tray| {
+                        if let Ok(mut counter) = tray.refresh_counter.lock() {
+                            *counter = counter.wrapping_add(1);
+                        }
+                    });
+                }
+            }
+
+            _ = retention_interval.tick() => {
+                let removed = history.lock().unwrap().enforce_retention();
+                if removed > 0 {
+                    log::info!("Dropped {} item(s) from history under the retention policy", removed);
+                    if let Some(ref handle) = tray_handle {
+                        handle.update(|tray| {
+                            if let Ok(mut counter) = tray.refresh_counter.lock() {
+                                *counter = counter.wrapping_add(1);
+                            }
+                        });
+                    }
+                }
+            }
+
+            Some(expiry) = sensitive_rx.recv() => {
+                // A queued item never touched the clipboard, and applying it since started a
+                // timer of its own, so only drop it if it is still waiting
+                let expired = if expiry.pending {
+                    history.lock().unwrap().get(&expiry.id).is_some_and(|item| item.pending)
+                } else {
+                    true
+                };
+
+                // Only touch the clipboard if the user hasn't copied something else since
+                let still_current = !expiry.pending
+                    && clipboard_manager
+                        .get_text_in(expiry.target)
+                        .map(|text| text == expiry.content)
+                        .unwrap_or(false);
+
+                if still_current {
+                    let result = match expiry.previous {
+                        Some(previous) if !previous.is_empty() => {
+                            clipboard_manager.set_text_in(expiry.target, previous, Some(format!("auto-clear of {}", expiry.id)))
+                        }
+                        _ => clipboard_manager.clear_in(expiry.target),
+                    };
+                    match result {
+                        Ok(()) => log::info!("✓ Cleared sensitive item from clipboard"),
+                        Err(e) => log::error!("Failed to clear sensitive item: {}", e),
+                    }
+                }
+
+                // Never leave sensitive content lying around in history
+                if expired && history.lock().unwrap().remove(&expiry.id).is_some() {
+                    if let Some(ref handle) = tray_handle {
+                        handle.update(|tray| {
+                            if let Ok(mut counter) = tray.refresh_counter.lock() {
+                                *counter = counter.wrapping_add(1);
+                            }
+                        });
+                    }
+                }
+            }
+
+            else => {
+                log::info!("All channels closed, shutting down");
+                break;
+            }
+        }
+    }
+}
+
+// Writes a remote item to the clipboard and schedules the auto-clear of sensitive items.
+// Returns what the clipboard held before and the selection written, so the write can be undone.
+fn apply_remote(
+    clipboard: &ClipboardManager,
+    item: &HistoryItem,
+    origin: String,
+    clear_after: u64,
+    sensitive_tx: &mpsc::UnboundedSender<SensitiveExpiry>,
+) -> Result<(Option<String>, SelectionTarget)> {
+    // Remote items may go to PRIMARY only, so what they replace is read from where they land
+    let target = clipboard.remote_target();
+    let previous = clipboard.get_text_in(target).ok();
+
+    clipboard.set_text_in(target, item.content.clone(), Some(origin))?;
+
+    if item.sensitive {
+        schedule_expiry(item, previous.clone(), target, false, clear_after, sensitive_tx);
+    }
+
+    Ok((previous, target))
+}
+
+// Starts the auto-clear timer of a sensitive item, unless auto-clear is off
+fn schedule_expiry(
+    item: &HistoryItem,
+    previous: Option<String>,
+    target: SelectionTarget,
+    pending: bool,
+    clear_after: u64,
+    sensitive_tx: &mpsc::UnboundedSender<SensitiveExpiry>,
+) {
+    if clear_after == 0 {
+        return;
+    }
+
+    log::info!("Sensitive item will be cleared in {}s", clear_after);
+    let expiry = SensitiveExpiry {
+        id: item.id.clone(),
+        content: item.content.clone(),
+        previous,
+        target,
+        pending,
+    };
+    let sensitive_tx = sensitive_tx.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_secs(clear_after)).await;
+        let _ = sensitive_tx.send(expiry);
+    });
+}
+
+// Names the room in connection notifications once more than one room is joined
+fn connection_message(message: &str, room: &str, rooms: &RoomRouter) -> String {
+    if rooms.is_multi_room() {
+        format!("{} ({})", message, room)
+    } else {
+        message.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::{ClipboardBackend, MemoryBackend, Selection};
+    use crate::config::{ClipboardBackendKind, RoutingRule, DEFAULT_ROOM};
+    use crate::history::Retention;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    // The event loop running against an in-memory clipboard and a single fake room
+    struct Harness {
+        backend: Arc<MemoryBackend>,
+        history: Arc<Mutex<ClipboardHistory>>,
+        outgoing: mpsc::UnboundedReceiver<OutgoingMessage>,
+        ws: mpsc::UnboundedSender<(String, WsEvent)>,
+        actions: mpsc::UnboundedSender<NotificationAction>,
+        task: tokio::task::JoinHandle<()>,
+    }
+
+    fn start() -> Harness {
+        let mut config = Config::default();
+        config.clipboard.backend = ClipboardBackendKind::Memory;
+        config.notifications.local_copy = false;
+        config.notifications.remote_update = false;
+        config.notifications.errors = false;
+
+        let backend = Arc::new(MemoryBackend::new());
+        let clipboard = Arc::new(ClipboardManager::with_backend(backend.clone(), &config.clipboard));
+        let (clipboard_tx, clipboard_rx) = mpsc::unbounded_channel();
+        clipboard.start_monitoring(clipboard_tx).unwrap();
+
+        let (room_tx, outgoing) = mpsc::unbounded_channel();
+        let mut rooms = RoomRouter::new(RoutingRule::All);
+        rooms.add_room(&config.room_profiles()[0], room_tx).unwrap();
+
+        let (ws_tx, ws_rx) = mpsc::unbounded_channel();
+        let (action_tx, action_rx) = mpsc::unbounded_channel();
+        let notifier = Notifier::new(&config.notifications, action_tx.clone()).unwrap();
+        let retention = Retention {
+            max_items: 50,
+            max_bytes: 0,
+            max_item_bytes: 0,
+            max_age: None,
+        };
+        let history = Arc::new(Mutex::new(ClipboardHistory::new(retention, false)));
+
+        let app = App {
+            config,
+            history: history.clone(),
+            clipboard,
+            rooms: Arc::new(rooms),
+            presence: Arc::new(Mutex::new(Presence::new())),
+            connections: Arc::new(Mutex::new(Connections::new())),
+            notifier,
+            tray: None,
+        };
+        let channels = Channels {
+            clipboard: clipboard_rx,
+            ws: ws_rx,
+            actions: action_rx,
+        };
+
+        Harness {
+            backend,
+            history,
+            outgoing,
+            ws: ws_tx,
+            actions: action_tx,
+            task: tokio::spawn(run(app, channels)),
+        }
+    }
+
+    fn remote_item(id: &str, content: &str) -> HistoryData {
+        HistoryData {
+            id: id.to_string(),
+            content: content.to_string(),
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            sensitive: false,
+            origin: Some(format!("origin-{}", id)),
+            device: None,
+            target: None,
+            pinned: false,
+        }
+    }
+
+    async fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+        timeout(Duration::from_secs(1), async {
+            while !done() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("timed out waiting for {}", what));
+    }
+
+    #[tokio::test]
+    async fn local_copy_is_sent_to_the_room() {
+        let mut harness = start();
+
+        harness.backend.write(Selection::Clipboard, "copied here").unwrap();
+
+        let message = timeout(Duration::from_secs(1), harness.outgoing.recv())
+            .await
+            .expect("no outgoing update")
+            .unwrap();
+        match message {
+            OutgoingMessage::ClipboardUpdate { content, target } => {
+                assert_eq!(content, "copied here");
+                assert_eq!(target, None);
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+        assert_eq!(harness.history.lock().unwrap().get_recent(1)[0].content, "copied here");
+        harness.task.abort();
+    }
+
+    #[tokio::test]
+    async fn remote_update_is_applied_without_echo() {
+        let mut harness = start();
+
+        harness
+            .ws
+            .send((DEFAULT_ROOM.to_string(), WsEvent::ClipboardUpdate(remote_item("r1", "from phone"))))
+            .unwrap();
+
+        wait_for("the remote item to reach the clipboard", || {
+            harness.backend.read(Selection::Clipboard).ok().as_deref() == Some("from phone")
+        })
+        .await;
+
+        // Writing the remote item is reported by the backend, but must not be sent back
+        assert!(timeout(Duration::from_millis(300), harness.outgoing.recv()).await.is_err());
+        let history = harness.history.lock().unwrap();
+        assert_eq!(history.get_recent(1)[0].id, "r1");
+        assert_eq!(history.get_recent(1)[0].room.as_deref(), Some(DEFAULT_ROOM));
+        drop(history);
+        harness.task.abort();
+    }
+
+    #[tokio::test]
+    async fn clear_lost_with_its_connection_does_not_hide_a_later_server_clear() {
+        let mut harness = start();
+        let history_len = |harness: &Harness| harness.history.lock().unwrap().get_recent(50).len();
+
+        harness
+            .actions
+            .send(NotificationAction::ClearHistory { include_pinned: false })
+            .unwrap();
+        let message = timeout(Duration::from_secs(1), harness.outgoing.recv())
+            .await
+            .expect("clear was not sent")
+            .unwrap();
+        assert!(matches!(message, OutgoingMessage::ClearHistory { include_pinned: false }));
+
+        // The connection drops before the server echoes the clear
+        harness.ws.send((DEFAULT_ROOM.to_string(), WsEvent::Disconnected)).unwrap();
+        harness
+            .ws
+            .send((DEFAULT_ROOM.to_string(), WsEvent::ClipboardUpdate(remote_item("r1", "after reconnect"))))
+            .unwrap();
+        wait_for("the remote item", || history_len(&harness) == 1).await;
+
+        // Another device clears the room; that must not be taken for the echo of ours
+        harness
+            .ws
+            .send((DEFAULT_ROOM.to_string(), WsEvent::ClearHistory { include_pinned: true }))
+            .unwrap();
+        wait_for("the server clear", || history_len(&harness) == 0).await;
+        harness.task.abort();
+    }
+}
@@ -0,0 +1,188 @@
+use crate::control::{self, ControlRequest, ControlResponse};
+use crate::export::{self, ExportFormat};
+use crate::history::{HistoryItem, SearchMode};
+use anyhow::{bail, Context, Result};
+use chrono::{Local, TimeZone};
+use std::io::Read;
+
+/// Runs a subcommand against the already running instance
+pub fn run(command: &str, args: &[String]) -> Result<()> {
+    let (request, json) = match (command, args.first().map(String::as_str)) {
+        ("status", _) => (ControlRequest::Status, false),
+        ("send", _) => (parse_send(args)?, false),
+        ("history", Some("search")) => parse_search(&args[1..])?,
+        ("history", Some("delete")) => match &args[1..] {
+            [id] => (ControlRequest::Delete { id: id.clone() }, false),
+            _ => bail!("Usage: corridor history delete <id>"),
+        },
+        ("history", Some("export")) => return export_history(&args[1..]),
+        ("history", Some("import")) => return import_history(&args[1..]),
+        ("history", _) => bail!("Usage: corridor history <search | delete | export | import> ..."),
+        _ => bail!("Unknown command: {} (see --help)", command),
+    };
+
+    let response = call(&request)?;
+
+    if json {
+        println!("{}", serde_json::to_string(&response.items)?);
+    } else if response.items.is_empty() {
+        println!("{}", response.message);
+    } else {
+        for item in &response.items {
+            print_item(item);
+        }
+    }
+    Ok(())
+}
+
+// Sends a request, exiting with the error message if the running instance refused it
+fn call(request: &ControlRequest) -> Result<ControlResponse> {
+    let response = control::request(request)?;
+    if !response.ok {
+        eprintln!("❌ {}", response.message);
+        std::process::exit(1);
+    }
+    Ok(response)
+}
+
+// corridor history export [--format json|csv|md] [--output <file>]
+fn export_history(args: &[String]) -> Result<()> {
+    let mut format = ExportFormat::Json;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => format = ExportFormat::parse(args.next().context("--format needs json, csv or md")?)?,
+            "--output" | "-o" => output = Some(args.next().context("--output needs a file")?.clone()),
+            _ => bail!("Usage: corridor history export [--format json|csv|md] [--output <file>]"),
+        }
+    }
+
+    let response = call(&ControlRequest::Export)?;
+    let text = export::export(&response.items, format)?;
+    match output {
+        Some(path) => {
+            std::fs::write(&path, text).with_context(|| format!("Failed to write {}", path))?;
+            println!("Exported {} to {}", response.message, path);
+        }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+// corridor history import [--format json|csv] <file | ->
+fn import_history(args: &[String]) -> Result<()> {
+    let mut format = None;
+    let mut path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => format = Some(ExportFormat::parse(args.next().context("--format needs json or csv")?)?),
+            _ if path.is_none() => path = Some(arg.clone()),
+            _ => bail!("Usage: corridor history import [--format json|csv] <file | ->"),
+        }
+    }
+
+    let path = path.context("Usage: corridor history import [--format json|csv] <file | ->")?;
+    let text = if path == "-" {
+        let mut text = String::new();
+        std::io::stdin()
+            .read_to_string(&mut text)
+            .context("Failed to read stdin")?;
+        text
+    } else {
+        std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path))?
+    };
+
+    let format = format.unwrap_or_else(|| ExportFormat::from_path(&path));
+    let items = export::import(&text, format)?;
+    println!("{}", call(&ControlRequest::Import { items })?.message);
+    Ok(())
+}
+
+// One line per item: ID (usable with `send --item`), time, source and a preview
+fn print_item(item: &HistoryItem) {
+    let time = Local
+        .timestamp_millis_opt(item.timestamp)
+        .single()
+        .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default();
+    let preview: String = item.content.lines().next().unwrap_or("").chars().take(60).collect();
+    match item.device_name {
+        Some(ref device) => println!("{}  {}  {}  (from {})", item.id, time, preview, device),
+        None => println!("{}  {}  {}", item.id, time, preview),
+    }
+}
+
+// corridor history search [--fuzzy | --regex] [--limit <n>] [--json] <query>
+fn parse_search(args: &[String]) -> Result<(ControlRequest, bool)> {
+    let mut mode = SearchMode::Substring;
+    let mut limit = 20;
+    let mut json = false;
+    let mut words = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fuzzy" => mode = SearchMode::Fuzzy,
+            "--regex" => mode = SearchMode::Regex,
+            "--json" => json = true,
+            "--limit" => {
+                limit = args
+                    .next()
+                    .context("--limit needs a number")?
+                    .parse()
+                    .context("--limit needs a number")?
+            }
+            _ => words.push(arg.clone()),
+        }
+    }
+
+    if words.is_empty() {
+        bail!("Usage: corridor history search [--fuzzy | --regex] [--limit <n>] [--json] <query>");
+    }
+
+    let request = ControlRequest::Search {
+        query: words.join(" "),
+        mode,
+        limit,
+    };
+    Ok((request, json))
+}
+
+// corridor send --to <device> [--item <id> | <text> | -]
+fn parse_send(args: &[String]) -> Result<ControlRequest> {
+    let mut device = None;
+    let mut item = None;
+    let mut words = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--to" => device = Some(args.next().context("--to needs a device name")?.clone()),
+            "--item" => item = Some(args.next().context("--item needs a history item ID")?.clone()),
+            _ => words.push(arg.clone()),
+        }
+    }
+
+    let device = device.context("Usage: corridor send --to <device> [--item <id> | <text> | -]")?;
+    let content = match words.as_slice() {
+        [] => None,
+        [dash] if dash == "-" => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .context("Failed to read stdin")?;
+            Some(text)
+        }
+        _ => Some(words.join(" ")),
+    };
+
+    Ok(ControlRequest::Send {
+        device,
+        item,
+        content,
+    })
+}
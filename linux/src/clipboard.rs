@@ -1,8 +1,29 @@
+mod memory;
+mod polling;
+mod wayland;
+mod x11;
+
+use crate::config::{ClipboardBackendKind, ClipboardConfig, SelectionTarget};
 use anyhow::{Context, Result};
-use arboard::Clipboard;
-use clipboard_master::{CallbackResult, ClipboardHandler, Master};
+pub use memory::MemoryBackend;
+use polling::PollingBackend;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
+use wayland::WaylandBackend;
+use x11::X11Backend;
+
+// How often the PRIMARY selection is sampled when primary sync is enabled
+const PRIMARY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// How often the event watcher is cross-checked against the actual clipboard content
+const FALLBACK_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+// Consecutive checks with unreported content before switching to polling
+const FALLBACK_MISSED_CHECKS: u32 = 2;
+// How long a remote write is remembered for echo suppression
+const ECHO_WINDOW: Duration = Duration::from_secs(10);
 
 #[derive(Debug, Clone)]
 pub enum ClipboardEvent {
@@ -10,130 +31,376 @@ pub enum ClipboardEvent {
     Error(String),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Selection {
+    Clipboard,
+    Primary,
+}
+
+/// Receives raw changes from a backend's watcher; returning false stops the watcher.
+pub type ChangeCallback = Box<dyn Fn(ClipboardEvent) -> bool + Send + Sync>;
+
+/// A system clipboard implementation that `ClipboardManager` reads, writes and watches.
+pub trait ClipboardBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn read(&self, selection: Selection) -> Result<String>;
+
+    fn write(&self, selection: Selection, text: &str) -> Result<()>;
+
+    fn clear(&self, selection: Selection) -> Result<()>;
+
+    /// Whether the system offers `selection` at all; compositors may lack the primary selection.
+    fn supports(&self, _selection: Selection) -> bool {
+        true
+    }
+
+    /// Starts watching the CLIPBOARD selection on a backend-owned thread.
+    fn watch(&self, on_change: ChangeCallback) -> Result<()>;
+}
+
 pub struct ClipboardManager {
-    clipboard: Arc<Mutex<Clipboard>>,
+    backend: Arc<dyn ClipboardBackend>,
     last_content: Arc<Mutex<String>>,
-    ignore_next: Arc<Mutex<bool>>,
+    remote_writes: Arc<Mutex<RemoteWrites>>,
+    last_primary: Arc<Mutex<String>>,
+    remote_target: SelectionTarget,
+    primary_debounce: Option<Duration>,
+    poll_interval: Duration,
+    polling_fallback: bool,
 }
 
 impl ClipboardManager {
-    pub fn new() -> Result<Self> {
-        let clipboard = Clipboard::new().context("Failed to create clipboard")?;
-        Ok(Self {
-            clipboard: Arc::new(Mutex::new(clipboard)),
+    pub fn new(config: &ClipboardConfig) -> Result<Self> {
+        let backend = create_backend(config)?;
+        log::info!("Using {} clipboard backend", backend.name());
+        Ok(Self::with_backend(backend, config))
+    }
+
+    pub fn with_backend(backend: Arc<dyn ClipboardBackend>, config: &ClipboardConfig) -> Self {
+        Self {
+            backend,
             last_content: Arc::new(Mutex::new(String::new())),
-            ignore_next: Arc::new(Mutex::new(false)),
-        })
+            remote_writes: Arc::new(Mutex::new(RemoteWrites::default())),
+            last_primary: Arc::new(Mutex::new(String::new())),
+            remote_target: config.remote_target,
+            primary_debounce: config
+                .sync_primary
+                .then(|| Duration::from_millis(config.primary_debounce_ms)),
+            poll_interval: Duration::from_millis(config.poll_interval_ms),
+            // Backends that already sample or write synchronously can't miss changes
+            polling_fallback: config.polling_fallback
+                && !matches!(
+                    config.backend,
+                    ClipboardBackendKind::Polling | ClipboardBackendKind::Memory
+                ),
+        }
     }
 
     pub fn get_text(&self) -> Result<String> {
-        let mut clipboard = self.clipboard.lock().unwrap();
-        clipboard
-            .get_text()
-            .context("Failed to get clipboard text")
+        self.get_text_in(SelectionTarget::Clipboard)
+    }
+
+    /// Where remote items are written, so the same selection can later be checked, restored
+    /// or cleared even when that isn't CLIPBOARD
+    pub fn remote_target(&self) -> SelectionTarget {
+        self.remote_target
+    }
+
+    /// Reads back what a write to `target` left behind: CLIPBOARD unless only PRIMARY was written
+    pub fn get_text_in(&self, target: SelectionTarget) -> Result<String> {
+        if target == SelectionTarget::Primary {
+            self.backend
+                .read(Selection::Primary)
+                .context("Failed to get primary selection text")
+        } else {
+            self.backend
+                .read(Selection::Clipboard)
+                .context("Failed to get clipboard text")
+        }
     }
 
-    pub fn set_text(&self, text: String, from_remote: bool) -> Result<()> {
-        if from_remote {
-            *self.ignore_next.lock().unwrap() = true;
+    /// Writes `text` to the clipboard. Writes with an `origin` (the message or action that
+    /// produced them) are not reported back as local changes.
+    pub fn set_text(&self, text: String, origin: Option<String>) -> Result<()> {
+        // Local writes always go to CLIPBOARD; remote items honor the configured target
+        let target = if origin.is_some() {
+            self.remote_target
+        } else {
+            SelectionTarget::Clipboard
+        };
+        self.set_text_in(target, text, origin)
+    }
+
+    pub fn set_text_in(&self, target: SelectionTarget, text: String, origin: Option<String>) -> Result<()> {
+        if matches!(target, SelectionTarget::Primary | SelectionTarget::Both) {
+            // Record it first so the PRIMARY watcher doesn't treat our own write as a local change
+            *self.last_primary.lock().unwrap() = text.clone();
+            self.backend
+                .write(Selection::Primary, &text)
+                .context("Failed to set primary selection text")?;
+        }
+
+        if target == SelectionTarget::Primary {
+            return Ok(());
         }
 
-        {
-            let mut clipboard = self.clipboard.lock().unwrap();
-            clipboard
-                .set_text(&text)
-                .context("Failed to set clipboard text")?;
+        if let Some(origin) = origin {
+            self.remote_writes.lock().unwrap().record(&text, origin);
         }
 
+        self.backend
+            .write(Selection::Clipboard, &text)
+            .context("Failed to set clipboard text")?;
+
         *self.last_content.lock().unwrap() = text;
         Ok(())
     }
 
-    pub fn start_monitoring(self, tx: mpsc::UnboundedSender<ClipboardEvent>) -> Result<()> {
+    pub fn clear_in(&self, target: SelectionTarget) -> Result<()> {
+        if matches!(target, SelectionTarget::Primary | SelectionTarget::Both) {
+            self.backend
+                .clear(Selection::Primary)
+                .context("Failed to clear primary selection")?;
+            self.last_primary.lock().unwrap().clear();
+        }
+
+        if target == SelectionTarget::Primary {
+            return Ok(());
+        }
+
+        self.backend
+            .clear(Selection::Clipboard)
+            .context("Failed to clear clipboard")?;
+
+        self.last_content.lock().unwrap().clear();
+        Ok(())
+    }
+
+    pub fn start_monitoring(&self, tx: mpsc::UnboundedSender<ClipboardEvent>) -> Result<()> {
         let last_content = self.last_content.clone();
-        let ignore_next = self.ignore_next.clone();
+        let remote_writes = self.remote_writes.clone();
 
         if let Ok(initial) = self.get_text() {
             *last_content.lock().unwrap() = initial;
         }
 
+        if let Some(debounce) = self.primary_debounce {
+            Self::watch_primary(
+                self.backend.clone(),
+                self.last_primary.clone(),
+                debounce,
+                tx.clone(),
+            );
+        }
+
+        if self.polling_fallback {
+            self.watch_for_stalls(tx.clone());
+        }
+
+        log::info!("Starting clipboard monitoring...");
+        self.backend
+            .watch(change_callback(last_content, remote_writes, tx))
+    }
+
+    // Some desktops and nested X sessions never deliver change events. If the clipboard holds
+    // content we haven't reported across several checks, take over with a polling watcher.
+    // Both watchers may then fire for the same change, which `report_change` deduplicates.
+    fn watch_for_stalls(&self, tx: mpsc::UnboundedSender<ClipboardEvent>) {
+        let backend = self.backend.clone();
+        let last_content = self.last_content.clone();
+        let remote_writes = self.remote_writes.clone();
+        let poll_interval = self.poll_interval;
+
         std::thread::spawn(move || {
-            struct Handler {
-                tx: mpsc::UnboundedSender<ClipboardEvent>,
-                last_content: Arc<Mutex<String>>,
-                ignore_next: Arc<Mutex<bool>>,
+            let mut missed = 0;
+            while missed < FALLBACK_MISSED_CHECKS {
+                std::thread::sleep(FALLBACK_CHECK_INTERVAL);
+
+                let Ok(text) = backend.read(Selection::Clipboard) else {
+                    continue;
+                };
+                let reported = content_hash(&last_content.lock().unwrap());
+                if content_hash(&text) == reported {
+                    missed = 0;
+                } else {
+                    missed += 1;
+                }
             }
 
-            impl ClipboardHandler for Handler {
-                fn on_clipboard_change(&mut self) -> CallbackResult {
-                    let mut clipboard = match Clipboard::new() {
-                        Ok(cb) => cb,
-                        Err(e) => {
-                            log::error!("Failed to create clipboard: {}", e);
-                            return CallbackResult::Next;
-                        }
-                    };
-
-                    match clipboard.get_text() {
-                        Ok(text) => {
-                            let should_ignore = {
-                                let mut ignore = self.ignore_next.lock().unwrap();
-                                if *ignore {
-                                    *ignore = false;
-                                    true
-                                } else {
-                                    false
-                                }
-                            };
-
-                            if should_ignore {
-                                *self.last_content.lock().unwrap() = text;
-                                return CallbackResult::Next;
-                            }
+            log::warn!("Clipboard change events are not arriving, switching to polling");
+            polling::poll(
+                backend.as_ref(),
+                poll_interval,
+                change_callback(last_content, remote_writes, tx),
+            );
+        });
+    }
 
-                            let last = self.last_content.lock().unwrap().clone();
-                            if text != last {
-                                log::info!("Clipboard changed: {}...", &text.chars().take(50).collect::<String>());
-                                *self.last_content.lock().unwrap() = text.clone();
-                                if self.tx.send(ClipboardEvent::LocalChange(text)).is_err() {
-                                    log::error!("Failed to send clipboard event");
-                                    return CallbackResult::Stop;
-                                }
-                            }
+    // PRIMARY changes continuously while the user drag-selects, so a value is only reported
+    // once it has stayed the same for the debounce period.
+    fn watch_primary(
+        backend: Arc<dyn ClipboardBackend>,
+        last_primary: Arc<Mutex<String>>,
+        debounce: Duration,
+        tx: mpsc::UnboundedSender<ClipboardEvent>,
+    ) {
+        std::thread::spawn(move || {
+            if !backend.supports(Selection::Primary) {
+                log::warn!("Primary selection is not supported by the {} clipboard backend", backend.name());
+                return;
+            }
+            if let Ok(initial) = backend.read(Selection::Primary) {
+                *last_primary.lock().unwrap() = initial;
+            }
+
+            log::info!("Starting primary selection monitoring (debounce: {:?})...", debounce);
+            let mut candidate: Option<(String, Instant)> = None;
+            // An empty selection fails every read, so only the first failure in a row is logged
+            let mut failing = false;
+
+            loop {
+                std::thread::sleep(PRIMARY_POLL_INTERVAL);
+
+                let text = match backend.read(Selection::Primary) {
+                    Ok(text) if !text.is_empty() => text,
+                    Ok(_) => continue,
+                    Err(e) => {
+                        if let Some(arboard::Error::ClipboardNotSupported) = e.downcast_ref() {
+                            log::warn!("Primary selection is not supported by this clipboard backend");
+                            return;
                         }
-                        Err(e) => {
-                            log::warn!("Failed to get clipboard text: {}", e);
+                        if !failing {
+                            log::debug!("Failed to get primary selection text: {}", e);
+                            failing = true;
                         }
+                        continue;
                     }
-                    CallbackResult::Next
+                };
+                failing = false;
+
+                if *last_primary.lock().unwrap() == text {
+                    candidate = None;
+                    continue;
                 }
 
-                fn on_clipboard_error(&mut self, error: std::io::Error) -> CallbackResult {
-                    log::error!("Clipboard error: {}", error);
-                    let _ = self.tx.send(ClipboardEvent::Error(error.to_string()));
-                    CallbackResult::Next
+                match candidate {
+                    Some((ref pending, since)) if *pending == text => {
+                        if since.elapsed() >= debounce {
+                            log::info!("Primary selection changed: {}...", &text.chars().take(50).collect::<String>());
+                            *last_primary.lock().unwrap() = text.clone();
+                            candidate = None;
+                            if tx.send(ClipboardEvent::LocalChange(text)).is_err() {
+                                log::error!("Failed to send clipboard event");
+                                return;
+                            }
+                        }
+                    }
+                    _ => candidate = Some((text, Instant::now())),
                 }
             }
+        });
+    }
+}
 
-            let handler = Handler {
-                tx,
-                last_content,
-                ignore_next,
-            };
-
-            log::info!("Starting clipboard monitoring...");
-            match Master::new(handler) {
-                Ok(mut master) => {
-                    if let Err(e) = master.run() {
-                        log::error!("Clipboard monitoring stopped: {}", e);
+fn create_backend(config: &ClipboardConfig) -> Result<Arc<dyn ClipboardBackend>> {
+    Ok(match config.backend {
+        ClipboardBackendKind::Auto => {
+            // clipboard-master only sees changes while focused on most Wayland compositors,
+            // so prefer the data-control protocols whenever a Wayland session is available
+            if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+                match WaylandBackend::connect() {
+                    Ok(backend) => return Ok(Arc::new(backend)),
+                    Err(e) => {
+                        log::warn!("Wayland data-control unavailable, falling back to X11 clipboard: {}", e);
                     }
                 }
-                Err(e) => {
-                    log::error!("Failed to create clipboard master: {}", e);
-                }
             }
+            Arc::new(X11Backend::new()?)
+        }
+        ClipboardBackendKind::X11 => Arc::new(X11Backend::new()?),
+        ClipboardBackendKind::Wayland => Arc::new(WaylandBackend::connect()?),
+        ClipboardBackendKind::Polling => Arc::new(PollingBackend::new(Duration::from_millis(
+            config.poll_interval_ms,
+        ))?),
+        ClipboardBackendKind::Memory => Arc::new(MemoryBackend::new()),
+    })
+}
+
+fn content_hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn change_callback(
+    last_content: Arc<Mutex<String>>,
+    remote_writes: Arc<Mutex<RemoteWrites>>,
+    tx: mpsc::UnboundedSender<ClipboardEvent>,
+) -> ChangeCallback {
+    Box::new(move |event| match event {
+        ClipboardEvent::LocalChange(text) => report_change(text, &last_content, &remote_writes, &tx),
+        ClipboardEvent::Error(err) => tx.send(ClipboardEvent::Error(err)).is_ok(),
+    })
+}
+
+struct RemoteWrite {
+    hash: u64,
+    origin: String,
+    written_at: Instant,
+}
+
+// Content we wrote on behalf of remote items, oldest first. Matching by hash rather than
+// a single "ignore next event" flag means bursts of remote writes and duplicate change
+// events can't leak echoes or swallow a genuine local copy.
+#[derive(Default)]
+struct RemoteWrites {
+    entries: VecDeque<RemoteWrite>,
+}
+
+impl RemoteWrites {
+    fn record(&mut self, text: &str, origin: String) {
+        self.entries.push_back(RemoteWrite {
+            hash: content_hash(text),
+            origin,
+            written_at: Instant::now(),
         });
+    }
 
-        Ok(())
+    // Returns the origin if `text` is one of our own writes. Anything written before it
+    // has been superseded on the clipboard and is forgotten as well.
+    fn take(&mut self, text: &str) -> Option<String> {
+        self.entries
+            .retain(|entry| entry.written_at.elapsed() < ECHO_WINDOW);
+
+        let hash = content_hash(text);
+        let position = self.entries.iter().rposition(|entry| entry.hash == hash)?;
+        self.entries.drain(..=position).next_back().map(|entry| entry.origin)
+    }
+}
+
+// Forwards a freshly read clipboard value unless it is our own write or unchanged.
+// Returns false once the receiving side has gone away.
+fn report_change(
+    text: String,
+    last_content: &Mutex<String>,
+    remote_writes: &Mutex<RemoteWrites>,
+    tx: &mpsc::UnboundedSender<ClipboardEvent>,
+) -> bool {
+    if let Some(origin) = remote_writes.lock().unwrap().take(&text) {
+        log::debug!("Ignoring clipboard change caused by {}", origin);
+        *last_content.lock().unwrap() = text;
+        return true;
+    }
+
+    let last = last_content.lock().unwrap().clone();
+    if text != last {
+        log::info!("Clipboard changed: {}...", &text.chars().take(50).collect::<String>());
+        *last_content.lock().unwrap() = text.clone();
+        if tx.send(ClipboardEvent::LocalChange(text)).is_err() {
+            log::error!("Failed to send clipboard event");
+            return false;
+        }
     }
+    true
 }
@@ -0,0 +1,60 @@
+use super::{ChangeCallback, ClipboardBackend, ClipboardEvent, Selection};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// A process-local clipboard with no display dependency, for headless runs and tests.
+/// Every write is reported to the watcher, just like a real clipboard owner change.
+pub struct MemoryBackend {
+    contents: Mutex<HashMap<Selection, String>>,
+    watchers: Mutex<Vec<ChangeCallback>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self {
+            contents: Mutex::new(HashMap::new()),
+            watchers: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl ClipboardBackend for MemoryBackend {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn read(&self, selection: Selection) -> Result<String> {
+        self.contents
+            .lock()
+            .unwrap()
+            .get(&selection)
+            .cloned()
+            .context("Clipboard is empty")
+    }
+
+    fn write(&self, selection: Selection, text: &str) -> Result<()> {
+        self.contents
+            .lock()
+            .unwrap()
+            .insert(selection, text.to_string());
+
+        if selection == Selection::Clipboard {
+            self.watchers
+                .lock()
+                .unwrap()
+                .retain(|on_change| on_change(ClipboardEvent::LocalChange(text.to_string())));
+        }
+        Ok(())
+    }
+
+    fn clear(&self, selection: Selection) -> Result<()> {
+        self.contents.lock().unwrap().remove(&selection);
+        Ok(())
+    }
+
+    fn watch(&self, on_change: ChangeCallback) -> Result<()> {
+        self.watchers.lock().unwrap().push(on_change);
+        Ok(())
+    }
+}
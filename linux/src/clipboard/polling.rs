@@ -0,0 +1,70 @@
+use super::{content_hash, ChangeCallback, ClipboardBackend, ClipboardEvent, Selection, X11Backend};
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Reads and writes like the X11 backend, but detects changes by sampling the clipboard
+/// instead of relying on change notifications.
+pub struct PollingBackend {
+    inner: Arc<X11Backend>,
+    interval: Duration,
+}
+
+impl PollingBackend {
+    pub fn new(interval: Duration) -> Result<Self> {
+        Ok(Self {
+            inner: Arc::new(X11Backend::new()?),
+            interval,
+        })
+    }
+}
+
+impl ClipboardBackend for PollingBackend {
+    fn name(&self) -> &'static str {
+        "polling"
+    }
+
+    fn read(&self, selection: Selection) -> Result<String> {
+        self.inner.read(selection)
+    }
+
+    fn write(&self, selection: Selection, text: &str) -> Result<()> {
+        self.inner.write(selection, text)
+    }
+
+    fn clear(&self, selection: Selection) -> Result<()> {
+        self.inner.clear(selection)
+    }
+
+    fn watch(&self, on_change: ChangeCallback) -> Result<()> {
+        let inner: Arc<dyn ClipboardBackend> = self.inner.clone();
+        let interval = self.interval;
+        std::thread::spawn(move || poll(inner.as_ref(), interval, on_change));
+        Ok(())
+    }
+}
+
+// Samples CLIPBOARD every `interval` and reports it whenever its hash changes.
+// Only the hash of the last value is kept, so large clipboards aren't held twice.
+pub fn poll(backend: &dyn ClipboardBackend, interval: Duration, on_change: ChangeCallback) {
+    log::info!("Polling clipboard every {:?}", interval);
+    let mut last_hash = backend
+        .read(Selection::Clipboard)
+        .ok()
+        .map(|text| content_hash(&text));
+
+    loop {
+        std::thread::sleep(interval);
+
+        let Ok(text) = backend.read(Selection::Clipboard) else {
+            continue;
+        };
+        let hash = content_hash(&text);
+        if last_hash != Some(hash) {
+            last_hash = Some(hash);
+            if !on_change(ClipboardEvent::LocalChange(text)) {
+                break;
+            }
+        }
+    }
+}
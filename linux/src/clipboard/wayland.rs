@@ -0,0 +1,391 @@
+use super::{ChangeCallback, ClipboardBackend, ClipboardEvent, Selection};
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::io::Read;
+use std::os::fd::{AsFd, BorrowedFd};
+use std::sync::{Arc, Mutex};
+use wayland_client::Connection;
+
+// Text MIME types we offer and accept, in order of preference
+const TEXT_MIME_TYPES: &[&str] = &[
+    "text/plain;charset=utf-8",
+    "UTF8_STRING",
+    "text/plain",
+    "STRING",
+    "TEXT",
+];
+
+// State shared between the backend handle and the Wayland event thread
+struct Shared {
+    current: Mutex<HashMap<Selection, String>>,
+    on_change: Mutex<Option<ChangeCallback>>,
+}
+
+impl Shared {
+    fn on_selection(&self, selection: Selection, text: String) {
+        self.current
+            .lock()
+            .unwrap()
+            .insert(selection, text.clone());
+
+        if selection == Selection::Clipboard {
+            let mut on_change = self.on_change.lock().unwrap();
+            if let Some(ref callback) = *on_change {
+                if !callback(ClipboardEvent::LocalChange(text)) {
+                    *on_change = None;
+                }
+            }
+        }
+    }
+
+    fn on_cleared(&self, selection: Selection) {
+        self.current.lock().unwrap().remove(&selection);
+    }
+
+    // Asks the selection owner for the text via a pipe and reads it on a separate thread,
+    // so the event thread stays free to serve the request when we own the selection ourselves.
+    fn read_offer(
+        self: &Arc<Self>,
+        conn: &Connection,
+        selection: Selection,
+        mime_types: &[String],
+        receive: impl FnOnce(String, BorrowedFd),
+    ) {
+        let Some(mime) = TEXT_MIME_TYPES
+            .iter()
+            .find(|mime| mime_types.iter().any(|offered| offered == *mime))
+        else {
+            log::debug!("Ignoring non-text selection");
+            return;
+        };
+
+        let (mut reader, writer) = match std::io::pipe() {
+            Ok(pipe) => pipe,
+            Err(e) => {
+                log::warn!("Failed to create pipe for clipboard offer: {}", e);
+                return;
+            }
+        };
+
+        receive(mime.to_string(), writer.as_fd());
+        if let Err(e) = conn.flush() {
+            log::warn!("Failed to flush Wayland connection: {}", e);
+        }
+        drop(writer);
+
+        let shared = self.clone();
+        std::thread::spawn(move || {
+            let mut bytes = Vec::new();
+            match reader.read_to_end(&mut bytes) {
+                Ok(_) => match String::from_utf8(bytes) {
+                    Ok(text) if !text.is_empty() => shared.on_selection(selection, text),
+                    Ok(_) => {}
+                    Err(_) => log::warn!("Clipboard offer was not valid UTF-8"),
+                },
+                Err(e) => log::warn!("Failed to read clipboard offer: {}", e),
+            }
+        });
+    }
+}
+
+// ext-data-control and wlr-data-control are the same protocol under different names,
+// so the backend is written once and instantiated for each of them.
+macro_rules! data_control_backend {
+    ($primary_since:literal) => {
+        use super::{Selection, Shared, TEXT_MIME_TYPES};
+        use anyhow::Result;
+        use std::io::Write;
+        use std::sync::{Arc, Mutex};
+        use wayland_client::globals::{registry_queue_init, GlobalListContents};
+        use wayland_client::protocol::{wl_registry::WlRegistry, wl_seat::WlSeat};
+        use wayland_client::{event_created_child, Connection, Dispatch, Proxy, QueueHandle};
+
+        pub struct State {
+            shared: Arc<Shared>,
+        }
+
+        pub struct Backend {
+            conn: Connection,
+            qh: QueueHandle<State>,
+            manager: Manager,
+            device: Device,
+        }
+
+        impl Backend {
+            pub fn connect(conn: &Connection, shared: Arc<Shared>) -> Result<Self> {
+                let (globals, mut queue) = registry_queue_init::<State>(conn)?;
+                let qh = queue.handle();
+                let manager: Manager = globals.bind(&qh, 1..=$primary_since, ())?;
+                let seat: WlSeat = globals.bind(&qh, 1..=1, ())?;
+                let device = manager.get_data_device(&seat, &qh, ());
+
+                let mut state = State { shared };
+                std::thread::spawn(move || loop {
+                    if let Err(e) = queue.blocking_dispatch(&mut state) {
+                        log::error!("Wayland clipboard connection lost: {}", e);
+                        break;
+                    }
+                });
+
+                Ok(Self {
+                    conn: conn.clone(),
+                    qh,
+                    manager,
+                    device,
+                })
+            }
+
+            pub fn write(&self, selection: Selection, text: &str) -> Result<()> {
+                let source = self.manager.create_data_source(&self.qh, text.to_string());
+                for mime in TEXT_MIME_TYPES {
+                    source.offer(mime.to_string());
+                }
+                self.set(selection, Some(&source))
+            }
+
+            pub fn clear(&self, selection: Selection) -> Result<()> {
+                self.set(selection, None)
+            }
+
+            pub fn supports(&self, selection: Selection) -> bool {
+                selection == Selection::Clipboard || self.manager.version() >= $primary_since
+            }
+
+            fn set(&self, selection: Selection, source: Option<&Source>) -> Result<()> {
+                match selection {
+                    Selection::Clipboard => self.device.set_selection(source),
+                    Selection::Primary if self.supports(selection) => {
+                        self.device.set_primary_selection(source)
+                    }
+                    Selection::Primary => {
+                        anyhow::bail!("Compositor does not support the primary selection")
+                    }
+                }
+                self.conn.flush()?;
+                Ok(())
+            }
+        }
+
+        impl Dispatch<WlRegistry, GlobalListContents> for State {
+            fn event(
+                _: &mut Self,
+                _: &WlRegistry,
+                _: <WlRegistry as Proxy>::Event,
+                _: &GlobalListContents,
+                _: &Connection,
+                _: &QueueHandle<Self>,
+            ) {
+            }
+        }
+
+        impl Dispatch<WlSeat, ()> for State {
+            fn event(
+                _: &mut Self,
+                _: &WlSeat,
+                _: <WlSeat as Proxy>::Event,
+                _: &(),
+                _: &Connection,
+                _: &QueueHandle<Self>,
+            ) {
+            }
+        }
+
+        impl Dispatch<Manager, ()> for State {
+            fn event(
+                _: &mut Self,
+                _: &Manager,
+                _: <Manager as Proxy>::Event,
+                _: &(),
+                _: &Connection,
+                _: &QueueHandle<Self>,
+            ) {
+            }
+        }
+
+        impl Dispatch<Device, ()> for State {
+            fn event(
+                state: &mut Self,
+                device: &Device,
+                event: device::Event,
+                _: &(),
+                conn: &Connection,
+                _: &QueueHandle<Self>,
+            ) {
+                match event {
+                    device::Event::Selection { id: Some(offer) } => {
+                        read(state, conn, Selection::Clipboard, offer);
+                    }
+                    device::Event::Selection { id: None } => {
+                        state.shared.on_cleared(Selection::Clipboard);
+                    }
+                    device::Event::PrimarySelection { id: Some(offer) } => {
+                        read(state, conn, Selection::Primary, offer);
+                    }
+                    device::Event::PrimarySelection { id: None } => {
+                        state.shared.on_cleared(Selection::Primary);
+                    }
+                    device::Event::Finished => {
+                        log::error!("Wayland clipboard device was invalidated");
+                        device.destroy();
+                    }
+                    _ => {}
+                }
+            }
+
+            event_created_child!(State, Device, [
+                device::EVT_DATA_OFFER_OPCODE => (Offer, Mutex::new(Vec::<String>::new())),
+            ]);
+        }
+
+        fn read(state: &State, conn: &Connection, selection: Selection, offer: Offer) {
+            let mime_types = offer
+                .data::<Mutex<Vec<String>>>()
+                .map(|mimes| mimes.lock().unwrap().clone())
+                .unwrap_or_default();
+            state
+                .shared
+                .read_offer(conn, selection, &mime_types, |mime, fd| offer.receive(mime, fd));
+            offer.destroy();
+        }
+
+        impl Dispatch<Offer, Mutex<Vec<String>>> for State {
+            fn event(
+                _: &mut Self,
+                _: &Offer,
+                event: offer::Event,
+                mime_types: &Mutex<Vec<String>>,
+                _: &Connection,
+                _: &QueueHandle<Self>,
+            ) {
+                if let offer::Event::Offer { mime_type } = event {
+                    mime_types.lock().unwrap().push(mime_type);
+                }
+            }
+        }
+
+        impl Dispatch<Source, String> for State {
+            fn event(
+                _: &mut Self,
+                source: &Source,
+                event: source::Event,
+                text: &String,
+                _: &Connection,
+                _: &QueueHandle<Self>,
+            ) {
+                match event {
+                    source::Event::Send { fd, .. } => {
+                        let text = text.clone();
+                        // Writing may block until the receiver reads, so keep it off the event thread
+                        std::thread::spawn(move || {
+                            let mut pipe = std::fs::File::from(fd);
+                            if let Err(e) = pipe.write_all(text.as_bytes()) {
+                                log::warn!("Failed to send clipboard content: {}", e);
+                            }
+                        });
+                    }
+                    source::Event::Cancelled => source.destroy(),
+                    _ => {}
+                }
+            }
+        }
+    };
+}
+
+mod ext {
+    use wayland_protocols::ext::data_control::v1::client::{
+        ext_data_control_device_v1::{self as device, ExtDataControlDeviceV1 as Device},
+        ext_data_control_manager_v1::ExtDataControlManagerV1 as Manager,
+        ext_data_control_offer_v1::{self as offer, ExtDataControlOfferV1 as Offer},
+        ext_data_control_source_v1::{self as source, ExtDataControlSourceV1 as Source},
+    };
+
+    data_control_backend!(1);
+}
+
+mod wlr {
+    use wayland_protocols_wlr::data_control::v1::client::{
+        zwlr_data_control_device_v1::{self as device, ZwlrDataControlDeviceV1 as Device},
+        zwlr_data_control_manager_v1::ZwlrDataControlManagerV1 as Manager,
+        zwlr_data_control_offer_v1::{self as offer, ZwlrDataControlOfferV1 as Offer},
+        zwlr_data_control_source_v1::{self as source, ZwlrDataControlSourceV1 as Source},
+    };
+
+    // The primary selection was added in version 2 of wlr-data-control
+    data_control_backend!(2);
+}
+
+enum Backend {
+    Ext(ext::Backend),
+    Wlr(wlr::Backend),
+}
+
+pub struct WaylandBackend {
+    backend: Backend,
+    shared: Arc<Shared>,
+}
+
+impl WaylandBackend {
+    pub fn connect() -> Result<Self> {
+        let conn = Connection::connect_to_env().context("Failed to connect to Wayland display")?;
+        let shared = Arc::new(Shared {
+            current: Mutex::new(HashMap::new()),
+            on_change: Mutex::new(None),
+        });
+
+        // Prefer the standardized protocol, fall back to the wlroots one
+        let backend = match ext::Backend::connect(&conn, shared.clone()) {
+            Ok(backend) => Backend::Ext(backend),
+            Err(_) => match wlr::Backend::connect(&conn, shared.clone()) {
+                Ok(backend) => Backend::Wlr(backend),
+                Err(_) => bail!("Compositor supports neither ext-data-control nor wlr-data-control"),
+            },
+        };
+
+        Ok(Self { backend, shared })
+    }
+}
+
+impl ClipboardBackend for WaylandBackend {
+    fn name(&self) -> &'static str {
+        match self.backend {
+            Backend::Ext(_) => "wayland (ext-data-control)",
+            Backend::Wlr(_) => "wayland (wlr-data-control)",
+        }
+    }
+
+    fn read(&self, selection: Selection) -> Result<String> {
+        self.shared
+            .current
+            .lock()
+            .unwrap()
+            .get(&selection)
+            .cloned()
+            .context("Selection is empty or holds no text")
+    }
+
+    fn write(&self, selection: Selection, text: &str) -> Result<()> {
+        match self.backend {
+            Backend::Ext(ref backend) => backend.write(selection, text),
+            Backend::Wlr(ref backend) => backend.write(selection, text),
+        }
+    }
+
+    fn clear(&self, selection: Selection) -> Result<()> {
+        match self.backend {
+            Backend::Ext(ref backend) => backend.clear(selection),
+            Backend::Wlr(ref backend) => backend.clear(selection),
+        }
+    }
+
+    fn supports(&self, selection: Selection) -> bool {
+        match self.backend {
+            Backend::Ext(ref backend) => backend.supports(selection),
+            Backend::Wlr(ref backend) => backend.supports(selection),
+        }
+    }
+
+    fn watch(&self, on_change: ChangeCallback) -> Result<()> {
+        *self.shared.on_change.lock().unwrap() = Some(on_change);
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+use super::{ChangeCallback, ClipboardBackend, ClipboardEvent, Selection};
+use anyhow::{Context, Result};
+use arboard::{ClearExtLinux, Clipboard, GetExtLinux, LinuxClipboardKind, SetExtLinux};
+use clipboard_master::{CallbackResult, ClipboardHandler, Master};
+use std::sync::Mutex;
+
+fn kind(selection: Selection) -> LinuxClipboardKind {
+    match selection {
+        Selection::Clipboard => LinuxClipboardKind::Clipboard,
+        Selection::Primary => LinuxClipboardKind::Primary,
+    }
+}
+
+/// arboard for reading and writing, clipboard-master for change notifications.
+pub struct X11Backend {
+    clipboard: Mutex<Clipboard>,
+}
+
+impl X11Backend {
+    pub fn new() -> Result<Self> {
+        let clipboard = Clipboard::new().context("Failed to create clipboard")?;
+        Ok(Self {
+            clipboard: Mutex::new(clipboard),
+        })
+    }
+}
+
+impl ClipboardBackend for X11Backend {
+    fn name(&self) -> &'static str {
+        "x11"
+    }
+
+    fn read(&self, selection: Selection) -> Result<String> {
+        let mut clipboard = self.clipboard.lock().unwrap();
+        Ok(clipboard.get().clipboard(kind(selection)).text()?)
+    }
+
+    fn write(&self, selection: Selection, text: &str) -> Result<()> {
+        let mut clipboard = self.clipboard.lock().unwrap();
+        clipboard.set().clipboard(kind(selection)).text(text)?;
+        Ok(())
+    }
+
+    fn clear(&self, selection: Selection) -> Result<()> {
+        let mut clipboard = self.clipboard.lock().unwrap();
+        clipboard.clear_with().clipboard(kind(selection))?;
+        Ok(())
+    }
+
+    fn watch(&self, on_change: ChangeCallback) -> Result<()> {
+        std::thread::spawn(move || {
+            struct Handler {
+                on_change: ChangeCallback,
+            }
+
+            impl ClipboardHandler for Handler {
+                fn on_clipboard_change(&mut self) -> CallbackResult {
+                    let mut clipboard = match Clipboard::new() {
+                        Ok(cb) => cb,
+                        Err(e) => {
+                            log::error!("Failed to create clipboard: {}", e);
+                            return CallbackResult::Next;
+                        }
+                    };
+
+                    match clipboard.get_text() {
+                        Ok(text) => {
+                            if !(self.on_change)(ClipboardEvent::LocalChange(text)) {
+                                return CallbackResult::Stop;
+                            }
+                        }
+                        Err(e) => {
+                            log::warn!("Failed to get clipboard text: {}", e);
+                        }
+                    }
+                    CallbackResult::Next
+                }
+
+                fn on_clipboard_error(&mut self, error: std::io::Error) -> CallbackResult {
+                    log::error!("Clipboard error: {}", error);
+                    (self.on_change)(ClipboardEvent::Error(error.to_string()));
+                    CallbackResult::Next
+                }
+            }
+
+            match Master::new(Handler { on_change }) {
+                Ok(mut master) => {
+                    if let Err(e) = master.run() {
+                        log::error!("Clipboard monitoring stopped: {}", e);
+                    }
+                }
+                Err(e) => {
+                    log::error!("Failed to create clipboard master: {}", e);
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
@@ -1,8 +1,12 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs;
 use std::path::PathBuf;
 
+/// Name of the room configured through the top-level `token`
+pub const DEFAULT_ROOM: &str = "default";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub token: String,
@@ -12,6 +16,87 @@ pub struct Config {
     pub auto_start: bool,
     pub notifications: NotificationConfig,
     pub clipboard: ClipboardConfig,
+    #[serde(default)]
+    pub device: DeviceConfig,
+    /// Additional rooms joined alongside the one configured by `token`
+    #[serde(default)]
+    pub rooms: Vec<RoomConfig>,
+    /// Which rooms local copies are shared with
+    #[serde(default)]
+    pub routing: RoutingRule,
+}
+
+/// A named room profile; the top-level `token` is always joined as the "default" room
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomConfig {
+    pub name: String,
+    pub token: String,
+    /// Defaults to the top-level `websocket_url`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub websocket_url: Option<String>,
+    /// Defaults to the top-level `http_url`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_url: Option<String>,
+    /// Regular expression a local copy must match to be shared here under the `filter` rule
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+}
+
+/// A room as joined at runtime, after defaults have been applied
+#[derive(Debug, Clone)]
+pub struct RoomProfile {
+    pub name: String,
+    pub token: String,
+    pub websocket_url: String,
+    pub http_url: String,
+    pub filter: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoutingRule {
+    /// Share every local copy with every room
+    #[default]
+    All,
+    /// Share local copies with the default room only; other rooms are receive-only
+    Default,
+    /// Share a copy with each room whose filter matches it, or the default room if none does
+    Filter,
+}
+
+/// Identifies this client to the other devices in the room
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceConfig {
+    /// Generated on first run and kept stable afterwards
+    #[serde(default)]
+    pub id: String,
+    /// Shown to other devices, defaults to the hostname
+    #[serde(default)]
+    pub name: String,
+    /// Whether this device shares local copies, applies remote items, or both
+    #[serde(default)]
+    pub direction: SyncDirection,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncDirection {
+    #[default]
+    Both,
+    /// Shares local copies but never writes remote items to the clipboard (e.g. build servers)
+    SendOnly,
+    /// Applies remote items but never shares local copies (e.g. shared or kiosk machines)
+    ReceiveOnly,
+}
+
+impl SyncDirection {
+    pub fn sends(self) -> bool {
+        self != SyncDirection::ReceiveOnly
+    }
+
+    pub fn receives(self) -> bool {
+        self != SyncDirection::SendOnly
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,11 +111,142 @@ pub struct NotificationConfig {
     pub local_copy: bool,
     pub remote_update: bool,
     pub errors: bool,
+    /// Remote items from one device arriving within this window share a notification (0 = off)
+    #[serde(default = "default_coalesce_window_ms")]
+    pub coalesce_window_ms: u64,
+    /// Local time range during which no notifications are shown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub do_not_disturb: Option<QuietHours>,
+    /// Drop notifications while the session is idle or the screen is locked; they aren't shown later
+    #[serde(default = "default_suppress_when_idle")]
+    pub suppress_when_idle: bool,
+}
+
+/// Start and end as "HH:MM"; the range may wrap around midnight
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuietHours {
+    pub start: String,
+    pub end: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClipboardConfig {
     pub history_size: usize,
+    /// Total bytes of history content to keep (0 = unlimited)
+    #[serde(default)]
+    pub history_max_bytes: usize,
+    /// Items larger than this many bytes are not kept in history (0 = unlimited)
+    #[serde(default)]
+    pub history_max_item_bytes: usize,
+    /// Hours after which items drop out of history (0 = keep until evicted)
+    #[serde(default)]
+    pub history_max_age_hours: u64,
+    /// Copying content already anywhere in history moves that entry to the top instead of adding another
+    #[serde(default)]
+    pub dedupe_history: bool,
+    /// Largest item sent or accepted, in bytes (0 = unlimited)
+    #[serde(default = "default_max_sync_bytes")]
+    pub max_sync_bytes: usize,
+    /// Items longer than this once escaped for JSON are sent in chunks of at most this
+    /// escaped length, to servers that reassemble them (0 = never chunk)
+    #[serde(default = "default_chunk_bytes")]
+    pub chunk_bytes: usize,
+    /// Messages above this many bytes are compressed if the server supports it (0 = never compress)
+    #[serde(default = "default_compress_bytes")]
+    pub compress_bytes: usize,
+    /// Seconds after which a remote item flagged as sensitive is cleared (0 = never)
+    #[serde(default = "default_sensitive_clear_secs")]
+    pub sensitive_clear_secs: u64,
+    /// Also watch the X11 PRIMARY selection (middle-click paste) for local changes
+    #[serde(default)]
+    pub sync_primary: bool,
+    /// How long the PRIMARY selection must stay unchanged before it is synced
+    #[serde(default = "default_primary_debounce_ms")]
+    pub primary_debounce_ms: u64,
+    /// Which selection(s) remote items are written to
+    #[serde(default)]
+    pub remote_target: SelectionTarget,
+    #[serde(default)]
+    pub backend: ClipboardBackendKind,
+    /// Sampling interval for the polling watcher
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+    /// Switch to polling when change events stop arriving while the clipboard keeps changing
+    #[serde(default = "default_polling_fallback")]
+    pub polling_fallback: bool,
+    /// Keep remote items in history until they are applied from a notification or the tray
+    #[serde(default)]
+    pub manual_accept: bool,
+    /// Ask before clearing history from the tray
+    #[serde(default)]
+    pub confirm_clear: bool,
+    /// How long a clear from the tray can be undone (0 = no undo)
+    #[serde(default = "default_clear_undo_secs")]
+    pub clear_undo_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardBackendKind {
+    /// Wayland data-control when available, X11 otherwise
+    #[default]
+    Auto,
+    X11,
+    Wayland,
+    Polling,
+    /// In-process clipboard with no display, for headless runs and tests
+    Memory,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SelectionTarget {
+    #[default]
+    Clipboard,
+    Primary,
+    Both,
+}
+
+fn default_coalesce_window_ms() -> u64 {
+    2000
+}
+
+fn default_suppress_when_idle() -> bool {
+    true
+}
+
+fn default_sensitive_clear_secs() -> u64 {
+    30
+}
+
+fn default_clear_undo_secs() -> u64 {
+    10
+}
+
+fn default_max_sync_bytes() -> usize {
+    16 * 1024 * 1024
+}
+
+// Chunks are measured after JSON escaping, so with the message around them they stay well
+// under the server's 1 MiB WebSocket message limit
+fn default_chunk_bytes() -> usize {
+    256 * 1024
+}
+
+fn default_compress_bytes() -> usize {
+    1024
+}
+
+fn default_primary_debounce_ms() -> u64 {
+    500
+}
+
+fn default_poll_interval_ms() -> u64 {
+    500
+}
+
+fn default_polling_fallback() -> bool {
+    true
 }
 
 impl Default for Config {
@@ -45,8 +261,33 @@ impl Default for Config {
                 local_copy: false,
                 remote_update: true,
                 errors: true,
+                coalesce_window_ms: default_coalesce_window_ms(),
+                do_not_disturb: None,
+                suppress_when_idle: default_suppress_when_idle(),
+            },
+            clipboard: ClipboardConfig {
+                history_size: 100,
+                history_max_bytes: 0,
+                history_max_item_bytes: 0,
+                history_max_age_hours: 0,
+                dedupe_history: false,
+                max_sync_bytes: default_max_sync_bytes(),
+                chunk_bytes: default_chunk_bytes(),
+                compress_bytes: default_compress_bytes(),
+                sensitive_clear_secs: default_sensitive_clear_secs(),
+                sync_primary: false,
+                primary_debounce_ms: default_primary_debounce_ms(),
+                remote_target: SelectionTarget::Clipboard,
+                backend: ClipboardBackendKind::Auto,
+                poll_interval_ms: default_poll_interval_ms(),
+                polling_fallback: default_polling_fallback(),
+                manual_accept: false,
+                confirm_clear: false,
+                clear_undo_secs: default_clear_undo_secs(),
             },
-            clipboard: ClipboardConfig { history_size: 100 },
+            device: DeviceConfig::default(),
+            rooms: Vec::new(),
+            routing: RoutingRule::All,
         }
     }
 }
@@ -74,9 +315,77 @@ impl Config {
             .with_context(|| format!("Failed to read config file: {:?}", path))?;
         let config: Config = serde_json::from_str(&content)
             .with_context(|| format!("Failed to parse config file: {:?}", path))?;
+        config.validate_rooms()?;
         Ok(config)
     }
 
+    // Room names key presence, history and routing, so each has to be unique
+    fn validate_rooms(&self) -> Result<()> {
+        let mut names = HashSet::new();
+        for room in &self.rooms {
+            if room.name == DEFAULT_ROOM {
+                bail!("Room name '{}' is reserved for the room joined with the top-level token", DEFAULT_ROOM);
+            }
+            if !names.insert(room.name.as_str()) {
+                bail!("Room '{}' is configured more than once", room.name);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::config_path()?;
+        let content = serde_json::to_string_pretty(self).context("Failed to serialize config")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write config file: {:?}", path))?;
+        Ok(())
+    }
+
+    /// Fills in a missing device ID and name, persisting them so they survive restarts
+    pub fn ensure_device_identity(&mut self) -> Result<()> {
+        let mut changed = false;
+
+        if self.device.id.is_empty() {
+            self.device.id = uuid::Uuid::new_v4().to_string();
+            changed = true;
+        }
+
+        if self.device.name.trim().is_empty() {
+            self.device.name = fs::read_to_string("/etc/hostname")
+                .ok()
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| "Linux".to_string());
+            changed = true;
+        }
+
+        if changed {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// All rooms to join, starting with the default room, with server URLs filled in
+    pub fn room_profiles(&self) -> Vec<RoomProfile> {
+        let default_room = RoomProfile {
+            name: DEFAULT_ROOM.to_string(),
+            token: self.token.clone(),
+            websocket_url: self.websocket_url.clone(),
+            http_url: self.http_url.clone(),
+            filter: None,
+        };
+
+        std::iter::once(default_room)
+            .chain(self.rooms.iter().map(|room| RoomProfile {
+                name: room.name.clone(),
+                token: room.token.clone(),
+                websocket_url: room.websocket_url.clone().unwrap_or_else(|| self.websocket_url.clone()),
+                http_url: room.http_url.clone().unwrap_or_else(|| self.http_url.clone()),
+                filter: room.filter.clone(),
+            }))
+            .collect()
+    }
+
     pub fn is_configured(&self) -> bool {
         !self.token.is_empty()
     }
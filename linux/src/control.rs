@@ -0,0 +1,325 @@
+use crate::clipboard::ClipboardManager;
+use crate::config::{Config, SyncDirection};
+use crate::heartbeat::format_latency;
+use crate::history::{ClipboardHistory, HistoryItem, SearchMode};
+use crate::rooms::{find_device, Connections, Presence, RoomRouter};
+use crate::websocket::OutgoingMessage;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+
+/// Requests accepted on the control socket, one JSON object per line
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlRequest {
+    /// Connection state and latency of each room, plus online devices and history size
+    Status,
+    /// Send content to a single device; defaults to the current clipboard
+    Send {
+        device: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        item: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        content: Option<String>,
+    },
+    /// Search local history, best matches first
+    Search {
+        query: String,
+        #[serde(default)]
+        mode: SearchMode,
+        #[serde(default = "default_search_limit")]
+        limit: usize,
+    },
+    /// Delete a history item locally and on the server of its room
+    Delete { id: String },
+    /// All of local history except sensitive items, newest first
+    Export,
+    /// Add archived items to local history, skipping ones already present
+    Import { items: Vec<HistoryItem> },
+}
+
+fn default_search_limit() -> usize {
+    20
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ControlResponse {
+    pub ok: bool,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<HistoryItem>,
+}
+
+impl ControlResponse {
+    fn ok(message: impl Into<String>) -> Self {
+        Self {
+            ok: true,
+            message: message.into(),
+            items: Vec::new(),
+        }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            message: message.into(),
+            items: Vec::new(),
+        }
+    }
+}
+
+pub fn socket_path() -> Result<PathBuf> {
+    match dirs::runtime_dir() {
+        Some(dir) => Ok(dir.join("corridor.sock")),
+        None => Ok(Config::config_dir()?.join("corridor.sock")),
+    }
+}
+
+/// Sends a single request to the running instance and waits for its response
+pub fn request(request: &ControlRequest) -> Result<ControlResponse> {
+    let path = socket_path()?;
+    let mut stream = std::os::unix::net::UnixStream::connect(&path)
+        .with_context(|| format!("Corridor does not appear to be running (no socket at {:?})", path))?;
+
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    stream
+        .write_all(line.as_bytes())
+        .context("Failed to send request")?;
+
+    let mut response = String::new();
+    BufReader::new(stream)
+        .read_line(&mut response)
+        .context("Failed to read response")?;
+    serde_json::from_str(&response).context("Invalid response from corridor")
+}
+
+/// Serves CLI requests against the running instance's state
+pub struct ControlServer {
+    history: Arc<Mutex<ClipboardHistory>>,
+    presence: Arc<Mutex<Presence>>,
+    connections: Arc<Mutex<Connections>>,
+    clipboard: Arc<ClipboardManager>,
+    rooms: Arc<RoomRouter>,
+    direction: SyncDirection,
+}
+
+impl ControlServer {
+    pub fn new(
+        history: Arc<Mutex<ClipboardHistory>>,
+        presence: Arc<Mutex<Presence>>,
+        connections: Arc<Mutex<Connections>>,
+        clipboard: Arc<ClipboardManager>,
+        rooms: Arc<RoomRouter>,
+        direction: SyncDirection,
+    ) -> Self {
+        Self {
+            history,
+            presence,
+            connections,
+            clipboard,
+            rooms,
+            direction,
+        }
+    }
+
+    pub fn spawn(self) -> Result<()> {
+        let path = socket_path()?;
+        // The single-instance lock guarantees any existing socket is stale
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("Failed to bind control socket: {:?}", path))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        }
+
+        log::info!("Control socket listening on {:?}", path);
+        let server = Arc::new(self);
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let server = server.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = server.handle_connection(stream).await {
+                                log::warn!("Control connection error: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        log::error!("Control socket stopped: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn handle_connection(&self, stream: UnixStream) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+
+        while let Some(line) = lines.next_line().await? {
+            let response = match serde_json::from_str::<ControlRequest>(&line) {
+                Ok(request) => self.handle(request),
+                Err(e) => ControlResponse::error(format!("Invalid request: {}", e)),
+            };
+
+            let mut line = serde_json::to_string(&response)?;
+            line.push('\n');
+            writer.write_all(line.as_bytes()).await?;
+        }
+
+        Ok(())
+    }
+
+    fn handle(&self, request: ControlRequest) -> ControlResponse {
+        match request {
+            ControlRequest::Status => self.status(),
+            ControlRequest::Send {
+                device,
+                item,
+                content,
+            } => self.send_to_device(&device, item, content),
+            ControlRequest::Search { query, mode, limit } => self.search(&query, mode, limit),
+            ControlRequest::Delete { id } => self.delete(&id),
+            ControlRequest::Export => self.export(),
+            ControlRequest::Import { items } => self.import(items),
+        }
+    }
+
+    fn status(&self) -> ControlResponse {
+        let mut lines = Vec::new();
+        {
+            let connections = self.connections.lock().unwrap();
+            for room in self.rooms.rooms() {
+                let state = match connections.get(&room.name) {
+                    Some(Some(latency)) => format!("connected, {} round trip", format_latency(*latency)),
+                    Some(None) => "connected".to_string(),
+                    None => "disconnected".to_string(),
+                };
+                lines.push(format!("Room {}: {}", room.name, state));
+            }
+        }
+
+        let devices: usize = self.presence.lock().unwrap().values().map(Vec::len).sum();
+        lines.push(format!("{} other device(s) online", devices));
+
+        let history = self.history.lock().unwrap();
+        lines.push(format!(
+            "{} item(s) in history, {} waiting to sync",
+            history.all().len(),
+            history.pending_sync_count()
+        ));
+
+        ControlResponse::ok(lines.join("\n"))
+    }
+
+    fn send_to_device(
+        &self,
+        query: &str,
+        item: Option<String>,
+        content: Option<String>,
+    ) -> ControlResponse {
+        if !self.direction.sends() {
+            return ControlResponse::error("This device is receive-only and doesn't send items");
+        }
+
+        let Some((room, device)) = find_device(&self.presence.lock().unwrap(), query) else {
+            return ControlResponse::error(format!("No device named '{}' is online", query));
+        };
+
+        let content = match (item, content) {
+            (_, Some(content)) => content,
+            (Some(id), None) => {
+                let history = self.history.lock().unwrap();
+                match history.get(&id) {
+                    Some(item) => item.content.clone(),
+                    None => return ControlResponse::error(format!("No history item with ID '{}'", id)),
+                }
+            }
+            (None, None) => match self.clipboard.get_text() {
+                Ok(text) => text,
+                Err(e) => return ControlResponse::error(e.to_string()),
+            },
+        };
+
+        let message = OutgoingMessage::ClipboardUpdate {
+            content,
+            target: Some(device.id.clone()),
+        };
+        if !self.rooms.send(&room, message) {
+            return ControlResponse::error("Connection to the sync server is not available");
+        }
+
+        ControlResponse::ok(format!("Sent to {}", device.name))
+    }
+
+    fn search(&self, query: &str, mode: SearchMode, limit: usize) -> ControlResponse {
+        let history = self.history.lock().unwrap();
+        match history.search(query, mode, limit) {
+            Ok(items) => {
+                let message = match items.len() {
+                    0 => format!("No items match '{}'", query),
+                    count => format!("{} matching item(s)", count),
+                };
+                ControlResponse {
+                    items: items.into_iter().cloned().collect(),
+                    ..ControlResponse::ok(message)
+                }
+            }
+            Err(e) => ControlResponse::error(format!("{:#}", e)),
+        }
+    }
+
+    fn delete(&self, id: &str) -> ControlResponse {
+        let Some(item) = self.history.lock().unwrap().remove(id) else {
+            return ControlResponse::error(format!("No history item with ID '{}'", id));
+        };
+
+        let message = OutgoingMessage::Delete {
+            id: item.id.clone(),
+            content: item.content.clone(),
+        };
+        self.rooms.send_for_item(&item, message);
+        ControlResponse::ok(format!("Deleted {}", item.id))
+    }
+
+    fn export(&self) -> ControlResponse {
+        let history = self.history.lock().unwrap();
+        let items: Vec<HistoryItem> = history
+            .all()
+            .iter()
+            .filter(|item| !item.sensitive)
+            .cloned()
+            .collect();
+        let message = format!("{} item(s)", items.len());
+        ControlResponse {
+            items,
+            ..ControlResponse::ok(message)
+        }
+    }
+
+    fn import(&self, items: Vec<HistoryItem>) -> ControlResponse {
+        let total = items.len();
+        // History import never takes sensitive items, so count them apart from duplicates
+        let sensitive = items.iter().filter(|item| item.sensitive).count();
+        let added = self.history.lock().unwrap().import(items);
+        ControlResponse::ok(format!(
+            "Imported {} of {} item(s), skipped {} sensitive, {} already in history or beyond the history limit",
+            added,
+            total,
+            sensitive,
+            total - sensitive - added
+        ))
+    }
+}
@@ -0,0 +1,132 @@
+use crate::history::HistoryItem;
+use anyhow::{bail, Context, Result};
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+    Markdown,
+}
+
+impl ExportFormat {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            "md" | "markdown" => Ok(Self::Markdown),
+            _ => bail!("Unknown format '{}' (expected json, csv or md)", name),
+        }
+    }
+
+    /// Guesses the format of a file to import from its extension, JSON unless it ends in .csv
+    pub fn from_path(path: &str) -> Self {
+        if path.to_lowercase().ends_with(".csv") {
+            Self::Csv
+        } else {
+            Self::Json
+        }
+    }
+}
+
+// CSV needs a fixed set of columns, so optional fields are always written, empty when unset
+#[derive(Debug, Serialize, Deserialize)]
+struct CsvRecord {
+    id: String,
+    timestamp: i64,
+    source: Option<String>,
+    device_name: Option<String>,
+    room: Option<String>,
+    #[serde(default)]
+    pinned: bool,
+    content: String,
+}
+
+impl From<&HistoryItem> for CsvRecord {
+    fn from(item: &HistoryItem) -> Self {
+        Self {
+            id: item.id.clone(),
+            timestamp: item.timestamp,
+            source: item.source.clone(),
+            device_name: item.device_name.clone(),
+            room: item.room.clone(),
+            pinned: item.pinned,
+            content: item.content.clone(),
+        }
+    }
+}
+
+impl From<CsvRecord> for HistoryItem {
+    fn from(record: CsvRecord) -> Self {
+        let mut item = HistoryItem::from_remote(record.id, record.content, record.timestamp);
+        item.source = record.source;
+        item.device_name = record.device_name;
+        item.room = record.room;
+        item.pinned = record.pinned;
+        item
+    }
+}
+
+pub fn export(items: &[HistoryItem], format: ExportFormat) -> Result<String> {
+    match format {
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(items)? + "\n"),
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for item in items {
+                writer.serialize(CsvRecord::from(item))?;
+            }
+            Ok(String::from_utf8(writer.into_inner()?)?)
+        }
+        ExportFormat::Markdown => Ok(to_markdown(items)),
+    }
+}
+
+/// Reads items written by `export`. Markdown is meant for reading and can't be imported.
+pub fn import(text: &str, format: ExportFormat) -> Result<Vec<HistoryItem>> {
+    match format {
+        ExportFormat::Json => serde_json::from_str(text).context("Invalid JSON history export"),
+        ExportFormat::Csv => csv::Reader::from_reader(text.as_bytes())
+            .deserialize::<CsvRecord>()
+            .map(|record| Ok(record.context("Invalid CSV history export")?.into()))
+            .collect(),
+        ExportFormat::Markdown => bail!("Markdown exports can't be imported, use json or csv"),
+    }
+}
+
+fn to_markdown(items: &[HistoryItem]) -> String {
+    let mut out = String::from("# Clipboard History\n");
+    for item in items {
+        let time = Local
+            .timestamp_millis_opt(item.timestamp)
+            .single()
+            .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        let mut heading = time;
+        if let Some(ref device) = item.device_name {
+            heading.push_str(&format!(" · from {}", device));
+        }
+        if item.pinned {
+            heading.push_str(" · 📌 pinned");
+        }
+
+        // The fence has to be longer than any backtick run inside the content
+        let longest_run = item
+            .content
+            .split(|c| c != '`')
+            .map(str::len)
+            .max()
+            .unwrap_or(0);
+        let fence = "`".repeat(longest_run.max(2) + 1);
+
+        out.push_str(&format!(
+            "\n## {}\n\n`{}`\n\n{}text\n{}\n{}\n",
+            heading,
+            item.id,
+            fence,
+            item.content.trim_end_matches('\n'),
+            fence
+        ));
+    }
+    out
+}
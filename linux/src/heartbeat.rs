@@ -0,0 +1,131 @@
+use std::time::{Duration, Instant};
+
+// Ping interval on a fast connection, and the most it stretches to on a slow one
+const BASE_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_INTERVAL: Duration = Duration::from_secs(30);
+// Silence allowed before the connection is considered dead
+const MIN_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Measures round-trip time with application-level ping/pong messages and derives the ping
+/// interval and connection timeout from it, smoothing samples the way TCP does (RFC 6298)
+#[derive(Default)]
+pub struct Heartbeat {
+    next_seq: u64,
+    // The ping awaiting its pong and when it was sent
+    pending: Option<(u64, Instant)>,
+    smoothed: Option<Duration>,
+    variation: Duration,
+}
+
+impl Heartbeat {
+    /// Records a ping being sent and returns the sequence number to put in it
+    pub fn ping(&mut self) -> u64 {
+        self.next_seq += 1;
+        self.pending = Some((self.next_seq, Instant::now()));
+        self.next_seq
+    }
+
+    /// Records a pong, returning the smoothed round-trip time if it answered the pending
+    /// ping. Servers that don't echo the sequence number answer whichever ping is pending.
+    pub fn pong(&mut self, seq: Option<u64>) -> Option<Duration> {
+        let (pending_seq, sent) = self.pending?;
+        if seq.is_some_and(|seq| seq != pending_seq) {
+            return None;
+        }
+        self.pending = None;
+        self.sample(sent.elapsed());
+        self.smoothed
+    }
+
+    // Folds a round-trip sample into the smoothed time and its variation
+    fn sample(&mut self, sample: Duration) {
+        match self.smoothed {
+            None => {
+                self.smoothed = Some(sample);
+                self.variation = sample / 2;
+            }
+            Some(smoothed) => {
+                self.variation = (self.variation * 3 + smoothed.abs_diff(sample)) / 4;
+                self.smoothed = Some((smoothed * 7 + sample) / 8);
+            }
+        }
+    }
+
+    /// Time between pings, longer on slow connections so pings don't pile up
+    pub fn interval(&self) -> Duration {
+        match self.smoothed {
+            Some(smoothed) => (smoothed * 4).clamp(BASE_INTERVAL, MAX_INTERVAL),
+            None => BASE_INTERVAL,
+        }
+    }
+
+    /// How long without any message from the server before giving up on the connection
+    pub fn timeout(&self) -> Duration {
+        let expected = self.smoothed.unwrap_or_default() + self.variation * 4;
+        (self.interval() + expected).clamp(MIN_TIMEOUT, MAX_TIMEOUT)
+    }
+}
+
+/// Latency for display, e.g. "42 ms"
+pub fn format_latency(latency: Duration) -> String {
+    format!("{} ms", latency.as_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn first_sample_sets_the_latency() {
+        let mut heartbeat = Heartbeat::default();
+        heartbeat.sample(ms(100));
+
+        assert_eq!(heartbeat.smoothed, Some(ms(100)));
+        assert_eq!(heartbeat.variation, ms(50));
+    }
+
+    #[test]
+    fn later_samples_are_smoothed() {
+        let mut heartbeat = Heartbeat::default();
+        heartbeat.sample(ms(100));
+        heartbeat.sample(ms(180));
+
+        assert_eq!(heartbeat.smoothed, Some(ms(110)));
+        assert_eq!(heartbeat.variation, Duration::from_micros(57_500));
+    }
+
+    #[test]
+    fn pong_answers_only_the_pending_ping() {
+        let mut heartbeat = Heartbeat::default();
+        assert_eq!(heartbeat.pong(Some(1)), None);
+
+        let seq = heartbeat.ping();
+        assert_eq!(heartbeat.pong(Some(seq + 1)), None);
+        assert!(heartbeat.pong(Some(seq)).is_some());
+        assert_eq!(heartbeat.pong(Some(seq)), None);
+
+        // Servers that don't echo the sequence number answer whichever ping is pending
+        heartbeat.ping();
+        assert!(heartbeat.pong(None).is_some());
+    }
+
+    #[test]
+    fn interval_and_timeout_grow_with_latency_within_bounds() {
+        let mut heartbeat = Heartbeat::default();
+        assert_eq!(heartbeat.interval(), BASE_INTERVAL);
+        assert_eq!(heartbeat.timeout(), MIN_TIMEOUT);
+
+        heartbeat.sample(ms(4000));
+        assert_eq!(heartbeat.interval(), ms(16_000));
+        assert_eq!(heartbeat.timeout(), ms(28_000));
+
+        heartbeat.sample(ms(40_000));
+        assert_eq!(heartbeat.interval(), MAX_INTERVAL);
+        assert_eq!(heartbeat.timeout(), MAX_TIMEOUT);
+    }
+}
@@ -1,7 +1,10 @@
+use anyhow::{Context, Result};
 use chrono::Utc;
+use regex::RegexBuilder;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryItem {
@@ -10,6 +13,31 @@ pub struct HistoryItem {
     pub timestamp: i64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sensitive: bool,
+    /// Name of the device that copied a remote item
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_name: Option<String>,
+    /// Room the item was shared in; local items shared with several rooms have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub room: Option<String>,
+    /// Remote item received in manual-accept mode that hasn't been applied or dismissed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pending: bool,
+    /// Pinned items are exempt from retention limits and survive clears unless explicitly included
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// How many times this content was copied, counted when history deduplication is on
+    #[serde(default = "single_copy", skip_serializing_if = "is_single_copy")]
+    pub copy_count: u32,
+}
+
+fn single_copy() -> u32 {
+    1
+}
+
+fn is_single_copy(count: &u32) -> bool {
+    *count <= 1
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +53,12 @@ impl HistoryItem {
             content,
             timestamp: Utc::now().timestamp_millis(),
             source: Some("local".to_string()),
+            sensitive: false,
+            device_name: None,
+            room: None,
+            pending: false,
+            pinned: false,
+            copy_count: 1,
         }
     }
 
@@ -34,23 +68,70 @@ impl HistoryItem {
             content,
             timestamp,
             source: Some("remote".to_string()),
+            sensitive: false,
+            device_name: None,
+            room: None,
+            pending: false,
+            pinned: false,
+            copy_count: 1,
         }
     }
+}
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// Case-insensitive substring
+    #[default]
+    Substring,
+    /// The query's characters in order, not necessarily adjacent
+    Fuzzy,
+    /// Case-insensitive regular expression
+    Regex,
+}
+
+// A local copy and the server's record of it arrive with slightly different timestamps;
+// a duplicate within this window is the same copy, not a new one
+const SAME_COPY_WINDOW_MS: i64 = 5000;
+
+// How much match quality counts against recency when ranking search results
+const MATCH_WEIGHT: f64 = 0.7;
+
+/// Limits on what history keeps; pinned items are exempt from all of them
+#[derive(Debug, Clone, Copy)]
+pub struct Retention {
+    pub max_items: usize,
+    /// Total size of item contents in bytes (0 = unlimited)
+    pub max_bytes: usize,
+    /// Items larger than this are not kept (0 = unlimited)
+    pub max_item_bytes: usize,
+    /// Items copied longer ago than this are dropped
+    pub max_age: Option<Duration>,
+}
+
+// What a user-initiated clear removed, kept until its undo window ends
+struct ClearedHistory {
+    items: Vec<HistoryItem>,
+    expires: Instant,
 }
 
 pub struct ClipboardHistory {
     items: Vec<HistoryItem>,
-    max_items: usize,
+    retention: Retention,
+    // Treat any earlier entry with the same content as a re-copy, not only the latest one
+    dedupe: bool,
     pending_sync_queue: VecDeque<PendingSync>,
+    last_clear: Option<ClearedHistory>,
 }
 
 impl ClipboardHistory {
-    pub fn new(max_items: usize) -> Self {
+    pub fn new(retention: Retention, dedupe: bool) -> Self {
         Self {
             items: Vec::new(),
-            max_items,
+            retention,
+            dedupe,
             pending_sync_queue: VecDeque::new(),
+            last_clear: None,
         }
     }
 
@@ -73,23 +154,27 @@ impl ClipboardHistory {
         self.pending_sync_queue.len()
     }
 
-    pub fn add(&mut self, item: HistoryItem) {
-        // Only add if it's different from the most recent item (don't duplicate consecutively)
-        if self.items.is_empty() || self.items[0].content != item.content {
-            self.items.insert(0, item);
-
-            if self.items.len() > self.max_items {
-                self.items.truncate(self.max_items);
+    pub fn add(&mut self, mut item: HistoryItem) {
+        if self.dedupe {
+            // A re-copy replaces the earlier entry at the top, keeping its count and pin
+            if let Some(index) = self.items.iter().position(|existing| existing.content == item.content) {
+                let previous = self.items.remove(index);
+                item.copy_count = previous.copy_count + 1;
+                item.pinned |= previous.pinned;
             }
+        } else if self.items.first().is_some_and(|first| first.content == item.content) {
+            // Only add if it's different from the most recent item (don't duplicate consecutively)
+            return;
         }
-    }
 
-    pub fn add_local(&mut self, content: String) {
-        self.add(HistoryItem::new(content));
+        self.items.insert(0, item);
+        self.enforce_retention();
     }
 
-    pub fn add_remote(&mut self, id: String, content: String, timestamp: i64) {
-        self.add(HistoryItem::from_remote(id, content, timestamp));
+    pub fn add_local(&mut self, content: String, room: Option<String>) {
+        let mut item = HistoryItem::new(content);
+        item.room = room;
+        self.add(item);
     }
 
     pub fn get_recent(&self, count: usize) -> &[HistoryItem] {
@@ -97,7 +182,530 @@ impl ClipboardHistory {
         &self.items[..end]
     }
 
-    pub fn clear(&mut self) {
-        self.items.clear();
+    /// Items matching `query`, best first. Ranking combines how well an item matches with
+    /// how recently it was copied. Sensitive items are never returned.
+    pub fn search(&self, query: &str, mode: SearchMode, limit: usize) -> Result<Vec<&HistoryItem>> {
+        let regex = match mode {
+            SearchMode::Regex => Some(
+                RegexBuilder::new(query)
+                    .case_insensitive(true)
+                    .build()
+                    .context("Invalid search pattern")?,
+            ),
+            _ => None,
+        };
+        let query = query.to_lowercase();
+
+        let mut results: Vec<(f64, &HistoryItem)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| !item.sensitive)
+            .filter_map(|(position, item)| {
+                let quality = match regex {
+                    Some(ref regex) => regex_quality(regex, &item.content),
+                    None if mode == SearchMode::Fuzzy => fuzzy_quality(&query, &item.content.to_lowercase()),
+                    None => substring_quality(&query, &item.content.to_lowercase()),
+                }?;
+                // Items are newest first, so position is a stand-in for age
+                let recency = 1.0 / (1.0 + position as f64 / 10.0);
+                Some((MATCH_WEIGHT * quality + (1.0 - MATCH_WEIGHT) * recency, item))
+            })
+            .collect();
+
+        results.sort_by(|a, b| b.0.total_cmp(&a.0));
+        Ok(results.into_iter().take(limit).map(|(_, item)| item).collect())
+    }
+
+    pub fn all(&self) -> &[HistoryItem] {
+        &self.items
+    }
+
+    /// Adds archived items, skipping any already present by ID or content. Returns how many
+    /// were added and survived the history limit.
+    pub fn import(&mut self, items: Vec<HistoryItem>) -> usize {
+        let mut imported = Vec::new();
+        for mut item in items {
+            if item.sensitive
+                || self
+                    .items
+                    .iter()
+                    .any(|existing| existing.id == item.id || existing.content == item.content)
+            {
+                continue;
+            }
+            item.pending = false;
+            imported.push(item.id.clone());
+            self.items.push(item);
+        }
+
+        self.items.sort_by_key(|item| std::cmp::Reverse(item.timestamp));
+        self.enforce_retention();
+        self.items
+            .iter()
+            .filter(|item| imported.contains(&item.id))
+            .count()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&HistoryItem> {
+        self.items.iter().find(|item| item.id == id)
+    }
+
+    /// Marks a waiting remote item as handled; returns false if it wasn't waiting
+    pub fn clear_pending(&mut self, id: &str) -> bool {
+        match self.items.iter_mut().find(|item| item.id == id && item.pending) {
+            Some(item) => {
+                item.pending = false;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes an item by ID, returning it if it was in history
+    pub fn remove(&mut self, id: &str) -> Option<HistoryItem> {
+        let index = self.items.iter().position(|item| item.id == id)?;
+        Some(self.items.remove(index))
+    }
+
+    /// Removes the item another device deleted, matched like `set_pinned`
+    pub fn remove_matching(&mut self, id: &str, content: &str) -> Option<HistoryItem> {
+        let index = self.find(id, content)?;
+        Some(self.items.remove(index))
+    }
+
+    /// Clears history, keeping pinned items unless `include_pinned` is set. What was removed
+    /// can be restored with `undo_clear` for `undo_window`.
+    pub fn clear(&mut self, include_pinned: bool, undo_window: Duration) {
+        let (kept, removed) = std::mem::take(&mut self.items)
+            .into_iter()
+            .partition(|item| item.pinned && !include_pinned);
+        self.items = kept;
+        self.last_clear = (!undo_window.is_zero()).then(|| ClearedHistory {
+            items: removed,
+            expires: Instant::now() + undo_window,
+        });
+    }
+
+    pub fn can_undo_clear(&self) -> bool {
+        self.last_clear
+            .as_ref()
+            .is_some_and(|cleared| Instant::now() < cleared.expires)
+    }
+
+    /// Puts back what the last clear removed if its undo window is still open, returning
+    /// the restored items
+    pub fn undo_clear(&mut self) -> Option<Vec<HistoryItem>> {
+        if !self.can_undo_clear() {
+            return None;
+        }
+        let items = self.last_clear.take()?.items;
+        self.merge(items.clone());
+        Some(items)
+    }
+
+    pub fn pinned(&self) -> Vec<&HistoryItem> {
+        self.items.iter().filter(|item| item.pinned).collect()
+    }
+
+    /// Remote items waiting to be applied in manual-accept mode, newest first
+    pub fn pending(&self) -> Vec<&HistoryItem> {
+        self.items.iter().filter(|item| item.pending).collect()
+    }
+
+    /// Pins or unpins the item matching `id`, or failing that `content` (local items get their
+    /// server ID only on the server). Returns the matched item.
+    pub fn set_pinned(&mut self, id: &str, content: &str, pinned: bool) -> Option<HistoryItem> {
+        let index = self.find(id, content)?;
+        self.items[index].pinned = pinned;
+        let item = self.items[index].clone();
+        self.enforce_retention();
+        Some(item)
+    }
+
+    fn find(&self, id: &str, content: &str) -> Option<usize> {
+        self.items
+            .iter()
+            .position(|item| item.id == id)
+            .or_else(|| self.items.iter().position(|item| item.content == content))
+    }
+
+    /// Drops unpinned items that are too large or too old, then the oldest ones beyond the
+    /// item count or byte budget. Runs on every insert and periodically, since items age.
+    /// Returns how many items were dropped.
+    pub fn enforce_retention(&mut self) -> usize {
+        let Retention {
+            max_items,
+            max_bytes,
+            max_item_bytes,
+            max_age,
+        } = self.retention;
+        let cutoff = max_age.map(|age| Utc::now().timestamp_millis() - age.as_millis() as i64);
+
+        let before = self.items.len();
+        let mut count = 0;
+        let mut bytes = 0;
+        // Items are newest first, so the newest ones win the count and byte budget
+        self.items.retain(|item| {
+            if item.pinned {
+                return true;
+            }
+            let size = item.content.len();
+            if (max_item_bytes > 0 && size > max_item_bytes)
+                || cutoff.is_some_and(|cutoff| item.timestamp < cutoff)
+            {
+                return false;
+            }
+            count += 1;
+            bytes += size;
+            count <= max_items && (max_bytes == 0 || bytes <= max_bytes)
+        });
+        before - self.items.len()
+    }
+
+    /// Merges items from a server into history. Items already present by ID or content are
+    /// kept once, local-only items stay, and the result is ordered newest first. With
+    /// deduplication on, a newer copy of known content counts as a re-copy.
+    pub fn merge(&mut self, items: Vec<HistoryItem>) {
+        let dedupe = self.dedupe;
+        for item in items {
+            let known = self
+                .items
+                .iter_mut()
+                .find(|existing| existing.id == item.id || existing.content == item.content);
+            match known {
+                // Keep the latest timestamp so an item sorts where it was last copied
+                Some(existing) => {
+                    if dedupe && existing.id != item.id && item.timestamp > existing.timestamp + SAME_COPY_WINDOW_MS {
+                        // Copied again on another device: this is now the latest copy
+                        existing.id = item.id;
+                        existing.source = item.source;
+                        existing.device_name = item.device_name;
+                        existing.room = item.room;
+                        existing.copy_count += 1;
+                    }
+                    existing.timestamp = existing.timestamp.max(item.timestamp);
+                    // A pin made here may not have reached the server yet, so merges only add pins
+                    existing.pinned |= item.pinned;
+                }
+                None => self.items.push(item),
+            }
+        }
+        self.items.sort_by_key(|item| std::cmp::Reverse(item.timestamp));
+        self.enforce_retention();
+    }
+
+    /// Takes a room's complete server history: remote items of that room the server no longer
+    /// has are dropped, local items are kept, and the rest is merged
+    pub fn resync_room(&mut self, room: &str, items: Vec<HistoryItem>) {
+        self.items.retain(|existing| {
+            existing.room.as_deref() != Some(room)
+                || existing.source.as_deref() == Some("local")
+                || existing.pinned
+                || items.iter().any(|item| item.id == existing.id)
+        });
+        self.merge(items);
+    }
+
+    pub fn clear_room(&mut self, room: &str, include_pinned: bool) {
+        self.items
+            .retain(|item| item.room.as_deref() != Some(room) || (item.pinned && !include_pinned));
+    }
+}
+
+// Match quality scores are between 0 and 1; None means no match
+
+fn substring_quality(query: &str, content: &str) -> Option<f64> {
+    let position = content.find(query)?;
+    Some(if content.trim() == query {
+        1.0
+    } else if position == 0 {
+        0.9
+    } else if !content[..position].ends_with(char::is_alphanumeric) {
+        // Starts at a word boundary
+        0.8
+    } else {
+        0.6
+    })
+}
+
+// Rewards query characters that appear close together and early in the content
+fn fuzzy_quality(query: &str, content: &str) -> Option<f64> {
+    let mut query_chars = query.chars().peekable();
+    let mut first = None;
+    let mut last = 0;
+
+    for (index, ch) in content.chars().enumerate() {
+        match query_chars.peek() {
+            Some(&wanted) if wanted == ch => {
+                first.get_or_insert(index);
+                last = index;
+                query_chars.next();
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+
+    if query_chars.peek().is_some() {
+        return None;
+    }
+
+    let Some(first) = first else {
+        // Empty query matches everything equally
+        return Some(0.5);
+    };
+    let span = (last - first + 1) as f64;
+    let compactness = query.chars().count() as f64 / span;
+    let early = 1.0 / (1.0 + first as f64 / 20.0);
+    Some(0.8 * compactness + 0.2 * early)
+}
+
+fn regex_quality(regex: &regex::Regex, content: &str) -> Option<f64> {
+    let found = regex.find(content)?;
+    // Patterns that cover more of the item are better matches
+    let coverage = found.len() as f64 / content.len().max(1) as f64;
+    Some(0.5 + 0.5 * coverage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unlimited() -> Retention {
+        Retention {
+            max_items: 100,
+            max_bytes: 0,
+            max_item_bytes: 0,
+            max_age: None,
+        }
+    }
+
+    fn remote(id: &str, content: &str, timestamp: i64) -> HistoryItem {
+        HistoryItem::from_remote(id.to_string(), content.to_string(), timestamp)
+    }
+
+    fn contents(history: &ClipboardHistory) -> Vec<&str> {
+        history.all().iter().map(|item| item.content.as_str()).collect()
+    }
+
+    #[test]
+    fn merge_orders_newest_first_and_keeps_local_items() {
+        let mut history = ClipboardHistory::new(unlimited(), false);
+        history.add_local("local".to_string(), None);
+        let now = history.all()[0].timestamp;
+
+        history.merge(vec![remote("a", "older", now - 2000), remote("b", "newer", now + 2000)]);
+
+        assert_eq!(contents(&history), ["newer", "local", "older"]);
+    }
+
+    #[test]
+    fn merge_keeps_known_items_once() {
+        let mut history = ClipboardHistory::new(unlimited(), false);
+        history.merge(vec![remote("a", "one", 1000), remote("b", "two", 2000)]);
+
+        // The same items again, one by ID and one only by content
+        history.merge(vec![remote("a", "one", 1000), remote("c", "two", 2000)]);
+
+        assert_eq!(contents(&history), ["two", "one"]);
+        assert_eq!(history.all()[0].id, "b");
+    }
+
+    #[test]
+    fn merge_only_adds_pins() {
+        let mut history = ClipboardHistory::new(unlimited(), false);
+        history.merge(vec![remote("a", "one", 1000)]);
+        history.set_pinned("a", "one", true);
+
+        history.merge(vec![remote("a", "one", 1000)]);
+
+        assert!(history.get("a").unwrap().pinned);
+    }
+
+    #[test]
+    fn resync_drops_remote_items_the_server_no_longer_has() {
+        let mut history = ClipboardHistory::new(unlimited(), false);
+        let in_room = |id: &str, content: &str, timestamp: i64, room: &str| HistoryItem {
+            room: Some(room.to_string()),
+            ..remote(id, content, timestamp)
+        };
+        history.merge(vec![
+            in_room("stale", "stale", 1000, "work"),
+            in_room("kept", "kept", 2000, "work"),
+            in_room("pinned", "pinned", 3000, "work"),
+            in_room("other", "other room", 4000, "home"),
+        ]);
+        history.set_pinned("pinned", "pinned", true);
+        history.add_local("local".to_string(), Some("work".to_string()));
+
+        history.resync_room("work", vec![in_room("kept", "kept", 2000, "work"), in_room("new", "new", 5000, "work")]);
+
+        let mut left = contents(&history);
+        left.sort_unstable();
+        assert_eq!(left, ["kept", "local", "new", "other room", "pinned"]);
+    }
+
+    fn search<'a>(history: &'a ClipboardHistory, query: &str, mode: SearchMode) -> Vec<&'a str> {
+        history
+            .search(query, mode, 10)
+            .unwrap()
+            .into_iter()
+            .map(|item| item.content.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn search_matches_by_mode() {
+        let mut history = ClipboardHistory::new(unlimited(), false);
+        for content in ["docker compose up", "Dockerfile", "git commit -m fix", "port 8080"] {
+            history.add_local(content.to_string(), None);
+        }
+
+        let mut substring = search(&history, "DOCKER", SearchMode::Substring);
+        substring.sort_unstable();
+        assert_eq!(substring, ["Dockerfile", "docker compose up"]);
+        assert_eq!(search(&history, "dckr cmpse", SearchMode::Fuzzy), ["docker compose up"]);
+        assert_eq!(search(&history, r"^port \d+$", SearchMode::Regex), ["port 8080"]);
+        assert!(search(&history, "kubectl", SearchMode::Substring).is_empty());
+        assert!(history.search("(", SearchMode::Regex, 10).is_err());
+    }
+
+    #[test]
+    fn search_ranks_better_matches_first_and_skips_sensitive_items() {
+        let mut history = ClipboardHistory::new(unlimited(), false);
+        history.add_local("token".to_string(), None);
+        history.add_local("my token here".to_string(), None);
+        history.add(HistoryItem {
+            sensitive: true,
+            ..HistoryItem::new("token secret".to_string())
+        });
+
+        // The exact match wins over the newer partial one, and the sensitive item never shows
+        assert_eq!(search(&history, "token", SearchMode::Substring), ["token", "my token here"]);
+    }
+
+    #[test]
+    fn import_skips_known_and_sensitive_items() {
+        let mut history = ClipboardHistory::new(unlimited(), false);
+        history.merge(vec![remote("a", "one", 1000), remote("b", "two", 3000)]);
+
+        let imported = history.import(vec![
+            remote("a", "renamed", 500),
+            remote("x", "two", 4000),
+            HistoryItem {
+                sensitive: true,
+                ..remote("s", "secret", 2500)
+            },
+            HistoryItem {
+                pending: true,
+                ..remote("c", "three", 2000)
+            },
+        ]);
+
+        assert_eq!(imported, 1);
+        assert_eq!(contents(&history), ["two", "three", "one"]);
+        assert!(!history.get("c").unwrap().pending);
+    }
+
+    #[test]
+    fn import_counts_only_items_that_fit() {
+        let mut history = ClipboardHistory::new(Retention { max_items: 2, ..unlimited() }, false);
+        history.merge(vec![remote("a", "new", 5000)]);
+
+        let imported = history.import(vec![remote("b", "newer", 6000), remote("c", "old", 1000)]);
+
+        assert_eq!(imported, 1);
+        assert_eq!(contents(&history), ["newer", "new"]);
+    }
+
+    #[test]
+    fn retention_drops_the_oldest_items_over_the_byte_budget() {
+        let mut history = ClipboardHistory::new(Retention { max_bytes: 10, ..unlimited() }, false);
+        history.merge(vec![remote("a", "aaaa", 1000), remote("b", "bbbb", 2000)]);
+
+        history.merge(vec![remote("c", "cccc", 3000)]);
+
+        assert_eq!(contents(&history), ["cccc", "bbbb"]);
+    }
+
+    #[test]
+    fn retention_drops_items_over_the_size_limit() {
+        let mut history = ClipboardHistory::new(Retention { max_item_bytes: 4, ..unlimited() }, false);
+
+        history.merge(vec![remote("a", "small", 1000), remote("b", "tiny", 2000)]);
+
+        assert_eq!(contents(&history), ["tiny"]);
+    }
+
+    #[test]
+    fn retention_drops_items_past_the_age_limit() {
+        let max_age = Duration::from_secs(3600);
+        let mut history = ClipboardHistory::new(Retention { max_age: Some(max_age), ..unlimited() }, false);
+        let now = Utc::now().timestamp_millis();
+        history.merge(vec![remote("a", "recent", now - 1000)]);
+        history.items.push(remote("b", "stale", now - 2 * 3600 * 1000));
+
+        assert_eq!(history.enforce_retention(), 1);
+        assert_eq!(contents(&history), ["recent"]);
+    }
+
+    #[test]
+    fn retention_never_drops_pinned_items() {
+        let mut history = ClipboardHistory::new(
+            Retention {
+                max_items: 1,
+                max_bytes: 4,
+                max_item_bytes: 4,
+                max_age: Some(Duration::from_secs(60)),
+            },
+            false,
+        );
+        // Too old and too large to get in, unless pinned
+        history.items.push(remote("a", "old and large", 1000));
+        history.set_pinned("a", "old and large", true);
+
+        history.merge(vec![remote("b", "new", Utc::now().timestamp_millis())]);
+
+        assert_eq!(contents(&history), ["new", "old and large"]);
+    }
+
+    #[test]
+    fn recopy_moves_to_the_top_and_counts() {
+        let mut history = ClipboardHistory::new(unlimited(), true);
+        history.add_local("one".to_string(), None);
+        history.add_local("two".to_string(), None);
+        history.set_pinned("", "one", true);
+
+        history.add_local("one".to_string(), None);
+
+        assert_eq!(contents(&history), ["one", "two"]);
+        assert_eq!(history.all()[0].copy_count, 2);
+        assert!(history.all()[0].pinned);
+    }
+
+    #[test]
+    fn without_dedupe_only_consecutive_copies_collapse() {
+        let mut history = ClipboardHistory::new(unlimited(), false);
+        for content in ["one", "one", "two", "one"] {
+            history.add_local(content.to_string(), None);
+        }
+
+        assert_eq!(contents(&history), ["one", "two", "one"]);
+    }
+
+    #[test]
+    fn merge_counts_a_later_remote_copy_of_known_content() {
+        let mut history = ClipboardHistory::new(unlimited(), true);
+        history.merge(vec![remote("a", "one", 1000), remote("b", "two", 2000)]);
+
+        // The server's record of a copy we already have, then a real re-copy elsewhere
+        history.merge(vec![remote("echo", "one", 1000 + SAME_COPY_WINDOW_MS)]);
+        assert_eq!(history.get("a").unwrap().copy_count, 1);
+        history.merge(vec![remote("c", "one", 20_000)]);
+
+        assert_eq!(contents(&history), ["one", "two"]);
+        assert_eq!(history.all()[0].id, "c");
+        assert_eq!(history.all()[0].copy_count, 2);
     }
 }
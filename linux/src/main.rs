@@ -1,17 +1,30 @@
+mod app;
+mod cli;
 mod clipboard;
 mod config;
+mod control;
+mod export;
+mod heartbeat;
 mod history;
+mod notifications;
+mod rooms;
+mod transfer;
 mod tray;
 mod websocket;
 
 use anyhow::{Context, Result};
+use app::{App, Channels};
 use clipboard::{ClipboardEvent, ClipboardManager};
 use config::{AppMode, Config};
-use history::ClipboardHistory;
+use control::ControlServer;
+use history::{ClipboardHistory, Retention};
+use notifications::{NotificationAction, Notifier};
+use rooms::{Connections, Presence, RoomRouter};
 use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
+use transfer::TransferLimits;
 use tray::TrayIcon;
-use websocket::{WebSocketClient, WsEvent};
+use websocket::{DeviceInfo, OutgoingMessage, WebSocketClient, WsEvent};
 use std::io::Write;
 
 // Embed all Python dialog scripts
@@ -22,6 +35,9 @@ pub const SETTINGS_DIALOG: &str = include_str!("../dialogs/settings_dialog.py");
 pub const HELP_DIALOG: &str = include_str!("../dialogs/help_dialog.py");
 pub const ABOUT_DIALOG: &str = include_str!("../dialogs/about_dialog.py");
 pub const SHOW_HISTORY: &str = include_str!("../dialogs/show_history.py");
+pub const SEARCH_HISTORY: &str = include_str!("../dialogs/search_history.py");
+pub const CONFIRM_CLEAR_DIALOG: &str = include_str!("../dialogs/confirm_clear_dialog.py");
+
 
 // Helper function to extract embedded dialog to temp file
 pub fn extract_dialog(content: &str, name: &str) -> Result<std::path::PathBuf> {
@@ -55,6 +71,20 @@ async fn main() -> Result<()> {
         println!();
         println!("USAGE:");
         println!("    corridor [OPTIONS]");
+        println!("    corridor <COMMAND> [ARGS]");
+        println!();
+        println!("COMMANDS (require a running instance):");
+        println!("    status           Show connection state, latency and history size");
+        println!("    send --to <device> [--item <id> | <text> | -]");
+        println!("                     Send the clipboard, a history item or text to one device");
+        println!("    history search [--fuzzy | --regex] [--limit <n>] [--json] <query>");
+        println!("                     Search clipboard history, best matches first");
+        println!("    history delete <id>");
+        println!("                     Delete a history item here and on every device in its room");
+        println!("    history export [--format json|csv|md] [--output <file>]");
+        println!("                     Write clipboard history to stdout or a file");
+        println!("    history import [--format json|csv] <file | ->");
+        println!("                     Add items from an export, skipping ones already in history");
         println!();
         println!("OPTIONS:");
         println!("    -h, --help       Show this help message");
@@ -66,6 +96,15 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
+    // Subcommands talk to the already running instance over the control socket
+    if let Some(command) = args.get(1).filter(|arg| !arg.starts_with('-')) {
+        if let Err(e) = cli::run(command, &args[2..]) {
+            eprintln!("❌ {:#}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let is_debug = args.contains(&"--debug".to_string()) || args.contains(&"-d".to_string());
     let is_autostart = args.contains(&"--autostart".to_string());
 
@@ -152,7 +191,7 @@ async fn main() -> Result<()> {
 
                     // Find all corridor processes
                     let pgrep_output = Command::new("pgrep")
-                        .args(&["-f", "corridor"])
+                        .args(["-f", "corridor"])
                         .output();
 
                     if let Ok(result) = pgrep_output {
@@ -163,7 +202,7 @@ async fn main() -> Result<()> {
                                 if pid != current_pid {
                                     log::info!("Killing corridor process with PID: {}", pid);
                                     let _ = Command::new("kill")
-                                        .args(&["-9", &pid.to_string()])
+                                        .args(["-9", &pid.to_string()])
                                         .output();
                                 }
                             }
@@ -205,7 +244,7 @@ async fn main() -> Result<()> {
         }
     }
 
-    let config = Config::load().context("Failed to load configuration")?;
+    let mut config = Config::load().context("Failed to load configuration")?;
 
     if !config.is_configured() {
         eprintln!("❌ corridor is not configured.");
@@ -216,289 +255,138 @@ async fn main() -> Result<()> {
         std::process::exit(1);
     }
 
+    config
+        .ensure_device_identity()
+        .context("Failed to set up device identity")?;
+
     log::info!("Configuration loaded. Token: {}", &config.token);
+    log::info!("Device: {} ({}), direction: {:?}", config.device.name, config.device.id, config.device.direction);
     log::info!("Mode: {:?}", config.mode);
 
     // Create in-memory history (no local storage)
+    let retention = Retention {
+        max_items: config.clipboard.history_size,
+        max_bytes: config.clipboard.history_max_bytes,
+        max_item_bytes: config.clipboard.history_max_item_bytes,
+        max_age: (config.clipboard.history_max_age_hours > 0)
+            .then(|| std::time::Duration::from_secs(config.clipboard.history_max_age_hours * 3600)),
+    };
     let history = Arc::new(Mutex::new(
-        ClipboardHistory::new(config.clipboard.history_size)
+        ClipboardHistory::new(retention, config.clipboard.dedupe_history)
     ));
 
-    let clipboard_manager =
-        ClipboardManager::new().context("Failed to create clipboard manager")?;
+    let clipboard_manager = Arc::new(
+        ClipboardManager::new(&config.clipboard).context("Failed to create clipboard manager")?
+    );
 
-    let (clipboard_tx, mut clipboard_rx) = mpsc::unbounded_channel::<ClipboardEvent>();
-    let (ws_tx, mut ws_rx) = mpsc::unbounded_channel::<WsEvent>();
-    let (clipboard_to_ws_tx, clipboard_to_ws_rx) = mpsc::unbounded_channel::<String>();
+    let (clipboard_tx, clipboard_rx) = mpsc::unbounded_channel::<ClipboardEvent>();
+    // Events from every room's client, tagged with the room name
+    let (ws_tx, ws_rx) = mpsc::unbounded_channel::<(String, WsEvent)>();
+    let (action_tx, action_rx) = mpsc::unbounded_channel::<NotificationAction>();
+    let notifier = Notifier::new(&config.notifications, action_tx.clone())
+        .context("Failed to set up notifications")?;
 
     clipboard_manager
         .start_monitoring(clipboard_tx)
         .context("Failed to start clipboard monitoring")?;
 
-    let ws_client = WebSocketClient::new(config.token.clone(), config.websocket_url.clone());
-    let ws_handle = tokio::spawn(async move {
-        ws_client
-            .connect_and_run(clipboard_to_ws_rx, ws_tx)
-            .await
-    });
+    let device = DeviceInfo {
+        id: config.device.id.clone(),
+        name: config.device.name.clone(),
+    };
+
+    let limits = TransferLimits {
+        max_bytes: config.clipboard.max_sync_bytes,
+        chunk_bytes: config.clipboard.chunk_bytes,
+        compress_bytes: config.clipboard.compress_bytes,
+    };
+
+    // One WebSocket client per room
+    let mut router = RoomRouter::new(config.routing);
+    let mut ws_handles = Vec::new();
+    for profile in config.room_profiles() {
+        let (room_tx, room_rx) = mpsc::unbounded_channel::<OutgoingMessage>();
+        router.add_room(&profile, room_tx)?;
+
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel::<WsEvent>();
+        let ws_client = WebSocketClient::new(profile.token, profile.websocket_url, device.clone(), limits);
+        ws_handles.push(tokio::spawn(async move {
+            ws_client.connect_and_run(room_rx, event_tx).await
+        }));
+
+        let ws_tx = ws_tx.clone();
+        let room = profile.name;
+        ws_handles.push(tokio::spawn(async move {
+            while let Some(event) = event_rx.recv().await {
+                if ws_tx.send((room.clone(), event)).is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        }));
+    }
+    drop(ws_tx);
+    let rooms = Arc::new(router);
+    log::info!("Joined {} room(s), routing: {:?}", rooms.rooms().len(), config.routing);
+
+    // Other devices currently in each room, as last announced by the servers
+    let presence: Arc<Mutex<Presence>> = Arc::new(Mutex::new(Presence::new()));
+    let connections: Arc<Mutex<Connections>> = Arc::new(Mutex::new(Connections::new()));
+
+    let control_server = ControlServer::new(
+        history.clone(),
+        presence.clone(),
+        connections.clone(),
+        clipboard_manager.clone(),
+        rooms.clone(),
+        config.device.direction,
+    );
+    if let Err(e) = control_server.spawn() {
+        log::warn!("Command line control unavailable: {}", e);
+    }
 
-    let (tray_connected, tray_handle) = if matches!(config.mode, AppMode::Interactive) {
+    let tray = if matches!(config.mode, AppMode::Interactive) {
         let tray = TrayIcon::new(
             history.clone(),
-            Some(clipboard_to_ws_tx.clone()),
-            config.http_url.clone(),
-            config.token.clone(),
+            presence.clone(),
+            connections.clone(),
+            clipboard_manager.clone(),
+            rooms.clone(),
+            action_tx.clone(),
+            &config,
         );
         let connected_handle = tray.get_connected_handle();
         let handle = tray.spawn();
-        (Some(connected_handle), Some(handle))
+        Some((connected_handle, handle))
     } else {
-        (None, None)
+        None
     };
 
-    let clipboard_manager_for_remote = ClipboardManager::new()?;
-    let history_for_clipboard = history.clone();
-    let notify_enabled = config.notifications.remote_update;
-    let history_for_sync = history.clone();
-    let clipboard_to_ws_tx_for_sync = clipboard_to_ws_tx.clone();
+    let app = App {
+        config,
+        history,
+        clipboard: clipboard_manager,
+        rooms,
+        presence,
+        connections,
+        notifier,
+        tray,
+    };
+    let channels = Channels {
+        clipboard: clipboard_rx,
+        ws: ws_rx,
+        actions: action_rx,
+    };
 
     log::info!("✓ Corridor is running");
     println!("✓ Corridor clipboard sync is active");
     println!("Press Ctrl+C to stop");
 
-    loop {
-        tokio::select! {
-            Some(event) = clipboard_rx.recv() => {
-                match event {
-                    ClipboardEvent::LocalChange(content) => {
-                        log::info!("Local clipboard changed");
-
-                        // Check if this is different from the last item to avoid duplicates
-                        let should_send = {
-                            let hist = history.lock().unwrap();
-                            let recent = hist.get_recent(1);
-                            recent.is_empty() || recent[0].content != content
-                        };
-
-                        if should_send {
-                            history.lock().unwrap().add_local(content.clone());
-
-                            // Try to send to websocket, if fails add to queue
-                            if clipboard_to_ws_tx.send(content.clone()).is_err() {
-                                log::warn!("WebSocket not available, adding to sync queue");
-                                history.lock().unwrap().add_to_sync_queue(content.clone());
-                            }
-
-                            // Always trigger immediate tray update when history changes
-                            if let Some(ref handle) = tray_handle {
-                                handle.update(|tray| {
-                                    if let Ok(mut counter) = tray.refresh_counter.lock() {
-                                        *counter = counter.wrapping_add(1);
-                                    }
-                                });
-                            }
-
-                            if config.notifications.local_copy {
-                                notify("Copied", &format!("{}", &content.chars().take(50).collect::<String>()));
-                            }
-                        } else {
-                            log::debug!("Skipping duplicate local clipboard update");
-                        }
-                    }
-                    ClipboardEvent::Error(err) => {
-                        log::error!("Clipboard error: {}", err);
-                        if config.notifications.errors {
-                            notify("Clipboard Error", &err);
-                        }
-                    }
-                }
-            }
-
-            Some(event) = ws_rx.recv() => {
-                match event {
-                    WsEvent::Connected => {
-                        log::info!("✓ WebSocket connected");
-                        if let Some(ref tray_conn) = tray_connected {
-                            *tray_conn.lock().unwrap() = true;
-                        }
-
-                        // Trigger immediate tray update
-                        if let Some(ref handle) = tray_handle {
-                            handle.update(|tray| {
-                                if let Ok(mut counter) = tray.refresh_counter.lock() {
-                                    *counter = counter.wrapping_add(1);
-                                }
-                            });
-                        }
-
-                        // Sync pending items from queue
-                        let pending_items = {
-                            let mut hist = history_for_sync.lock().unwrap();
-                            let count = hist.pending_sync_count();
-                            if count > 0 {
-                                log::info!("Found {} pending items to sync", count);
-                                hist.get_pending_syncs()
-                            } else {
-                                Vec::new()
-                            }
-                        };
-
-                        if !pending_items.is_empty() {
-                            let count = pending_items.len();
-                            log::info!("Syncing {} queued items to server", count);
-                            for pending in pending_items {
-                                if let Err(e) = clipboard_to_ws_tx_for_sync.send(pending.content.clone()) {
-                                    log::error!("Failed to sync queued item: {}", e);
-                                    // Re-add to queue if failed
-                                    history_for_sync.lock().unwrap().add_to_sync_queue(pending.content);
-                                } else {
-                                    log::info!("✓ Synced queued item");
-                                }
-                            }
-                            notify("Corridor", &format!("Synced {} queued clipboard items", count));
-
-                            // Trigger immediate tray update to show pending count cleared
-                            if let Some(ref handle) = tray_handle {
-                                handle.update(|tray| {
-                                    if let Ok(mut counter) = tray.refresh_counter.lock() {
-                                        *counter = counter.wrapping_add(1);
-                                    }
-                                });
-                            }
-                        }
-
-                        if config.notifications.remote_update {
-                            notify("Corridor", "Connected to sync server");
-                        }
-                    }
-                    WsEvent::Disconnected => {
-                        log::warn!("✗ WebSocket disconnected");
-                        if let Some(ref tray_conn) = tray_connected {
-                            *tray_conn.lock().unwrap() = false;
-                        }
-
-                        // Trigger immediate tray update
-                        if let Some(ref handle) = tray_handle {
-                            handle.update(|tray| {
-                                if let Ok(mut counter) = tray.refresh_counter.lock() {
-                                    *counter = counter.wrapping_add(1);
-                                }
-                            });
-                        }
-
-                        if config.notifications.errors {
-                            notify("Corridor", "Disconnected from sync server");
-                        }
-                    }
-                    WsEvent::ClipboardUpdate(content) => {
-                        log::info!("Remote clipboard update received");
-
-                        // Check if this is different from current content to avoid duplicates
-                        let should_update = {
-                            let hist = history_for_clipboard.lock().unwrap();
-                            let recent = hist.get_recent(1);
-                            recent.is_empty() || recent[0].content != content
-                        };
-
-                        if should_update {
-                            if let Err(e) = clipboard_manager_for_remote.set_text(content.clone(), true) {
-                                log::error!("Failed to update local clipboard: {}", e);
-                            } else {
-                                history_for_clipboard.lock().unwrap().add_remote(
-                                    format!("remote-{}", chrono::Utc::now().timestamp_millis()),
-                                    content.clone(),
-                                    chrono::Utc::now().timestamp_millis()
-                                );
-                                log::info!("✓ Updated local clipboard from remote");
-
-                                // Trigger immediate tray update to show new history item
-                                if let Some(ref handle) = tray_handle {
-                                    handle.update(|tray| {
-                                        if let Ok(mut counter) = tray.refresh_counter.lock() {
-                                            *counter = counter.wrapping_add(1);
-                                        }
-                                    });
-                                }
+    app::run(app, channels).await;
 
-                                if notify_enabled {
-                                    notify("Remote Clipboard", &format!("{}", &content.chars().take(50).collect::<String>()));
-                                }
-                            }
-                        } else {
-                            log::debug!("Skipping duplicate clipboard update");
-                        }
-                    }
-                    WsEvent::ClipboardHistory(items) => {
-                        log::info!("Received {} history items from server", items.len());
-                        let mut hist = history.lock().unwrap();
-                        // Clear local history and replace with server history
-                        let _ = hist.clear();
-                        for item in items {
-                            hist.add_remote(item.id, item.content, item.timestamp);
-                        }
-                        drop(hist); // Release the lock before updating tray
-                        log::info!("✓ Local history synced with server");
-
-                        // Trigger immediate tray update to show new history
-                        if let Some(ref handle) = tray_handle {
-                            handle.update(|tray| {
-                                if let Ok(mut counter) = tray.refresh_counter.lock() {
-                                    *counter = counter.wrapping_add(1);
-                                }
-                            });
-                        }
-                    }
-                    WsEvent::ClearHistory => {
-                        log::info!("Clearing local history (server cleared)");
-                        let mut hist = history.lock().unwrap();
-                        hist.clear();
-                        drop(hist); // Release the lock before updating tray
-                        log::info!("✓ Local history cleared");
-
-                        // Trigger immediate tray update to show cleared history
-                        if let Some(ref handle) = tray_handle {
-                            handle.update(|tray| {
-                                if let Ok(mut counter) = tray.refresh_counter.lock() {
-                                    *counter = counter.wrapping_add(1);
-                                }
-                            });
-                        }
-
-                        if config.notifications.remote_update {
-                            notify("Corridor", "History cleared");
-                        }
-                    }
-                    WsEvent::Error(err) => {
-                        log::error!("WebSocket error: {}", err);
-                        if config.notifications.errors {
-                            notify("Connection Error", &err);
-                        }
-                    }
-                }
-            }
-
-            else => {
-                log::info!("All channels closed, shutting down");
-                break;
-            }
-        }
+    for handle in ws_handles {
+        handle.abort();
     }
-
-    ws_handle.abort();
     log::info!("Corridor stopped");
     Ok(())
 }
-
-fn notify(title: &str, body: &str) {
-    use notify_rust::Notification;
-
-    if let Err(e) = Notification::new()
-        .summary(title)
-        .body(body)
-        .icon("edit-copy")
-        .timeout(3000)
-        .show()
-    {
-        log::warn!("Failed to show notification: {}", e);
-    }
-}
@@ -0,0 +1,398 @@
+use crate::config::{NotificationConfig, SelectionTarget};
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveTime};
+use notify_rust::{Notification, Timeout};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+// How long plain and coalesced notifications stay on screen
+const DEFAULT_TIMEOUT_MS: u32 = 3000;
+
+/// A choice the user made on an actionable notification or in the tray
+#[derive(Debug, Clone)]
+pub enum NotificationAction {
+    /// Write a remote item that is waiting for acceptance to the clipboard
+    Apply(String),
+    /// Keep a waiting remote item in history without applying it
+    Dismiss(String),
+    /// Put back what the selection `target` held before remote item `id` replaced it
+    Undo { id: String, previous: String, target: SelectionTarget },
+    /// Write a history item to the clipboard again
+    CopyAgain(String),
+    /// Open the full history viewer for a room
+    OpenHistory(String),
+    /// Clear history here and on every room's server
+    ClearHistory { include_pinned: bool },
+    /// Restore what the last clear removed while its undo window is open
+    UndoClear,
+}
+
+// Title and source of a group of item notifications
+type BatchKey = (String, String);
+
+struct Queued {
+    body: String,
+    timeout: Timeout,
+    actions: Vec<(&'static str, NotificationAction)>,
+}
+
+/// Shows desktop notifications, honoring do-not-disturb hours and idle suppression and
+/// folding bursts of item notifications from one source into a single summary
+#[derive(Clone)]
+pub struct Notifier {
+    coalesce_window: Duration,
+    quiet_hours: Option<(NaiveTime, NaiveTime)>,
+    suppress_when_idle: bool,
+    // Item notifications waiting for their coalescing window to end
+    batches: Arc<Mutex<HashMap<BatchKey, Vec<Queued>>>>,
+    // Id of the actionable notification a thread is currently waiting on
+    waiting: Arc<Mutex<Option<u32>>>,
+    actions: mpsc::UnboundedSender<NotificationAction>,
+}
+
+impl Notifier {
+    pub fn new(
+        config: &NotificationConfig,
+        actions: mpsc::UnboundedSender<NotificationAction>,
+    ) -> Result<Self> {
+        let quiet_hours = config
+            .do_not_disturb
+            .as_ref()
+            .map(|hours| -> Result<_> { Ok((parse_time(&hours.start)?, parse_time(&hours.end)?)) })
+            .transpose()
+            .context("Invalid do-not-disturb hours")?;
+
+        Ok(Self {
+            coalesce_window: Duration::from_millis(config.coalesce_window_ms),
+            quiet_hours,
+            suppress_when_idle: config.suppress_when_idle,
+            batches: Arc::new(Mutex::new(HashMap::new())),
+            waiting: Arc::new(Mutex::new(None)),
+            actions,
+        })
+    }
+
+    pub fn notify(&self, title: &str, body: &str) {
+        self.show(
+            title.to_string(),
+            body.to_string(),
+            Timeout::Milliseconds(DEFAULT_TIMEOUT_MS),
+            Vec::new(),
+        );
+    }
+
+    /// Announces an item from `source`. Items from the same source arriving within the
+    /// coalescing window are shown as one "N items received from ..." notification, except
+    /// items waiting to be applied, whose Apply / Dismiss choice a summary can't offer.
+    pub fn notify_item(
+        &self,
+        title: &str,
+        source: &str,
+        body: &str,
+        timeout: Timeout,
+        actions: Vec<(&'static str, NotificationAction)>,
+    ) {
+        let awaits_choice = actions
+            .iter()
+            .any(|(_, action)| matches!(action, NotificationAction::Apply(_)));
+        if self.coalesce_window.is_zero() || awaits_choice {
+            self.show(title.to_string(), body.to_string(), timeout, actions);
+            return;
+        }
+
+        let key = (title.to_string(), source.to_string());
+        let queued = Queued {
+            body: body.to_string(),
+            timeout,
+            actions,
+        };
+
+        let first = {
+            let mut batches = self.batches.lock().unwrap();
+            let batch = batches.entry(key.clone()).or_default();
+            batch.push(queued);
+            batch.len() == 1
+        };
+
+        if first {
+            let notifier = self.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(notifier.coalesce_window).await;
+                notifier.flush(key);
+            });
+        }
+    }
+
+    fn flush(&self, key: BatchKey) {
+        let Some(batch) = self.batches.lock().unwrap().remove(&key) else {
+            return;
+        };
+        let (title, source) = key;
+        let shown = summarize(&source, batch);
+        self.show(title, shown.body, shown.timeout, shown.actions);
+    }
+
+    fn show(
+        &self,
+        title: String,
+        body: String,
+        timeout: Timeout,
+        actions: Vec<(&'static str, NotificationAction)>,
+    ) {
+        let quiet_hours = self.quiet_hours;
+        let suppress_when_idle = self.suppress_when_idle;
+        let waiting = self.waiting.clone();
+        let tx = self.actions.clone();
+
+        // Asking logind and waiting for an action both block, so keep them off the caller's thread
+        std::thread::spawn(move || {
+            if quiet_hours.is_some_and(|(start, end)| in_quiet_hours(Local::now().time(), start, end)) {
+                log::debug!("Do not disturb, suppressing notification: {}", title);
+                return;
+            }
+            if suppress_when_idle && session_idle_or_locked() {
+                log::debug!("Session idle or locked, suppressing notification: {}", title);
+                return;
+            }
+
+            let mut notification = Notification::new();
+            notification
+                .summary(&title)
+                .body(&body)
+                .icon("edit-copy")
+                .timeout(timeout);
+            for (index, (label, _)) in actions.iter().enumerate() {
+                notification.action(&index.to_string(), label);
+            }
+
+            match notification.show() {
+                Ok(handle) if !actions.is_empty() => {
+                    // Servers that keep expired notifications around never report them closed,
+                    // so only the newest one keeps a waiting thread: closing the previous one
+                    // ends its wait. Items still waiting to be applied stay in the tray menu.
+                    let id = handle.id();
+                    let previous = waiting.lock().unwrap().replace(id);
+                    if let Some(previous) = previous {
+                        close_notification(previous);
+                    }
+
+                    handle.wait_for_action(|key| {
+                        // Closing or expiring the notification reports "__closed", which matches no action
+                        let chosen = key
+                            .parse::<usize>()
+                            .ok()
+                            .and_then(|index| actions.into_iter().nth(index));
+                        if let Some((_, action)) = chosen {
+                            let _ = tx.send(action);
+                        }
+                    });
+
+                    let mut waiting = waiting.lock().unwrap();
+                    if *waiting == Some(id) {
+                        *waiting = None;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("Failed to show notification: {}", e),
+            }
+        });
+    }
+}
+
+// What a batch of item notifications is shown as: the item itself, or a summary of them all
+fn summarize(source: &str, mut batch: Vec<Queued>) -> Queued {
+    if batch.len() == 1 {
+        return batch.remove(0);
+    }
+
+    // Per-item actions like Undo don't make sense for a summary, opening history does
+    let count = batch.len();
+    let actions = batch
+        .pop()
+        .map(|last| {
+            last.actions
+                .into_iter()
+                .filter(|(_, action)| matches!(action, NotificationAction::OpenHistory(_)))
+                .collect()
+        })
+        .unwrap_or_default();
+    Queued {
+        body: format!("{} items received from {}", count, source),
+        timeout: Timeout::Milliseconds(DEFAULT_TIMEOUT_MS),
+        actions,
+    }
+}
+
+fn parse_time(text: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(text.trim(), "%H:%M")
+        .with_context(|| format!("Expected HH:MM, got '{}'", text))
+}
+
+// Quiet hours may wrap around midnight, e.g. 22:00-07:00
+fn in_quiet_hours(now: NaiveTime, start: NaiveTime, end: NaiveTime) -> bool {
+    if start <= end {
+        now >= start && now < end
+    } else {
+        now >= start || now < end
+    }
+}
+
+// Closes a notification shown earlier, whose handle its waiting thread owns
+fn close_notification(id: u32) {
+    use std::process::Command;
+
+    let output = Command::new("gdbus")
+        .args([
+            "call",
+            "--session",
+            "--dest=org.freedesktop.Notifications",
+            "--object-path=/org/freedesktop/Notifications",
+            "--method=org.freedesktop.Notifications.CloseNotification",
+            &id.to_string(),
+        ])
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => log::debug!(
+            "Failed to close notification {}: {}",
+            id,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(e) => log::debug!("Failed to close notification {}: {}", id, e),
+    }
+}
+
+// Asks logind whether the desktop reported the session as idle or locked
+fn session_idle_or_locked() -> bool {
+    use std::process::Command;
+
+    let session = std::env::var("XDG_SESSION_ID").unwrap_or_else(|_| "auto".to_string());
+    let output = Command::new("loginctl")
+        .args([
+            "show-session",
+            &session,
+            "--property=IdleHint",
+            "--property=LockedHint",
+            "--value",
+        ])
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .any(|line| line.trim() == "yes"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(text: &str) -> NaiveTime {
+        parse_time(text).unwrap()
+    }
+
+    fn notifier(coalesce_window_ms: u64) -> Notifier {
+        let config = NotificationConfig {
+            local_copy: true,
+            remote_update: true,
+            errors: true,
+            coalesce_window_ms,
+            do_not_disturb: None,
+            suppress_when_idle: false,
+        };
+        Notifier::new(&config, mpsc::unbounded_channel().0).unwrap()
+    }
+
+    fn queued(body: &str, actions: Vec<(&'static str, NotificationAction)>) -> Queued {
+        Queued {
+            body: body.to_string(),
+            timeout: Timeout::Never,
+            actions,
+        }
+    }
+
+    #[test]
+    fn quiet_hours_within_a_day() {
+        let (start, end) = (time("09:00"), time("17:00"));
+
+        assert!(in_quiet_hours(time("09:00"), start, end));
+        assert!(in_quiet_hours(time("12:30"), start, end));
+        assert!(!in_quiet_hours(time("17:00"), start, end));
+        assert!(!in_quiet_hours(time("08:59"), start, end));
+    }
+
+    #[test]
+    fn quiet_hours_wrapping_past_midnight() {
+        let (start, end) = (time("22:00"), time("07:00"));
+
+        assert!(in_quiet_hours(time("22:00"), start, end));
+        assert!(in_quiet_hours(time("23:59"), start, end));
+        assert!(in_quiet_hours(time("00:00"), start, end));
+        assert!(in_quiet_hours(time("06:59"), start, end));
+        assert!(!in_quiet_hours(time("07:00"), start, end));
+        assert!(!in_quiet_hours(time("12:00"), start, end));
+        assert!(!in_quiet_hours(time("21:59"), start, end));
+    }
+
+    #[test]
+    fn quiet_hours_starting_and_ending_together_are_empty() {
+        let start = time("08:00");
+
+        assert!(!in_quiet_hours(time("08:00"), start, start));
+        assert!(!in_quiet_hours(time("20:00"), start, start));
+    }
+
+    #[test]
+    fn quiet_hours_must_be_valid_times() {
+        assert!(parse_time("25:00").is_err());
+        assert!(parse_time("noon").is_err());
+        assert_eq!(parse_time(" 7:05 ").unwrap(), time("07:05"));
+    }
+
+    #[tokio::test]
+    async fn a_burst_from_one_source_is_queued_as_one_batch() {
+        let notifier = notifier(60_000);
+
+        for body in ["one", "two", "three"] {
+            notifier.notify_item("Remote Clipboard", "laptop", body, Timeout::Never, Vec::new());
+        }
+        notifier.notify_item("Remote Clipboard", "phone", "four", Timeout::Never, Vec::new());
+
+        let batches = notifier.batches.lock().unwrap();
+        let laptop = &batches[&("Remote Clipboard".to_string(), "laptop".to_string())];
+        let bodies: Vec<&str> = laptop.iter().map(|queued| queued.body.as_str()).collect();
+        assert_eq!(bodies, ["one", "two", "three"]);
+        assert_eq!(batches[&("Remote Clipboard".to_string(), "phone".to_string())].len(), 1);
+    }
+
+    #[test]
+    fn a_single_queued_item_is_shown_as_is() {
+        let shown = summarize("laptop", vec![queued("one", vec![("Copy Again", NotificationAction::CopyAgain("a".to_string()))])]);
+
+        assert_eq!(shown.body, "one");
+        assert_eq!(shown.timeout, Timeout::Never);
+        assert!(matches!(shown.actions[..], [("Copy Again", NotificationAction::CopyAgain(_))]));
+    }
+
+    #[test]
+    fn a_burst_is_summarized_keeping_only_open_history() {
+        let actions = || {
+            vec![
+                ("Copy Again", NotificationAction::CopyAgain("a".to_string())),
+                ("View History", NotificationAction::OpenHistory("default".to_string())),
+            ]
+        };
+        let batch = vec![queued("one", actions()), queued("two", actions()), queued("three", actions())];
+
+        let shown = summarize("laptop", batch);
+
+        assert_eq!(shown.body, "3 items received from laptop");
+        assert_eq!(shown.timeout, Timeout::Milliseconds(DEFAULT_TIMEOUT_MS));
+        assert!(matches!(shown.actions[..], [("View History", NotificationAction::OpenHistory(_))]));
+    }
+}
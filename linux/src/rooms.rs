@@ -0,0 +1,143 @@
+use crate::config::{RoomProfile, RoutingRule, DEFAULT_ROOM};
+use crate::history::HistoryItem;
+use crate::websocket::{DeviceInfo, OutgoingMessage};
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Other devices currently online, keyed by room name
+pub type Presence = BTreeMap<String, Vec<DeviceInfo>>;
+
+/// Rooms whose WebSocket is connected, with the round-trip time to their server once measured
+pub type Connections = BTreeMap<String, Option<Duration>>;
+
+/// A joined room and the channel to its WebSocket client
+pub struct Room {
+    pub name: String,
+    pub http_url: String,
+    pub token: String,
+    filter: Option<Regex>,
+    tx: mpsc::UnboundedSender<OutgoingMessage>,
+}
+
+/// Decides which rooms local copies are shared with and forwards messages to their clients
+pub struct RoomRouter {
+    rooms: Vec<Room>,
+    rule: RoutingRule,
+}
+
+impl RoomRouter {
+    pub fn new(rule: RoutingRule) -> Self {
+        Self {
+            rooms: Vec::new(),
+            rule,
+        }
+    }
+
+    pub fn add_room(
+        &mut self,
+        profile: &RoomProfile,
+        tx: mpsc::UnboundedSender<OutgoingMessage>,
+    ) -> Result<()> {
+        let filter = profile
+            .filter
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .with_context(|| format!("Invalid filter for room '{}'", profile.name))?;
+
+        self.rooms.push(Room {
+            name: profile.name.clone(),
+            http_url: profile.http_url.clone(),
+            token: profile.token.clone(),
+            filter,
+            tx,
+        });
+        Ok(())
+    }
+
+    pub fn rooms(&self) -> &[Room] {
+        &self.rooms
+    }
+
+    /// The room configured through the top-level `token`, which is always added first
+    pub fn default_room(&self) -> &Room {
+        &self.rooms[0]
+    }
+
+    pub fn room(&self, name: &str) -> Option<&Room> {
+        self.rooms.iter().find(|room| room.name == name)
+    }
+
+    /// Whether more than the default room is joined, i.e. whether room names are worth showing
+    pub fn is_multi_room(&self) -> bool {
+        self.rooms.len() > 1
+    }
+
+    /// Names of the rooms a local copy of `content` should be shared with
+    pub fn route(&self, content: &str) -> Vec<String> {
+        let matches = |room: &&Room| match self.rule {
+            RoutingRule::All => true,
+            RoutingRule::Default => room.name == DEFAULT_ROOM,
+            RoutingRule::Filter => room
+                .filter
+                .as_ref()
+                .is_some_and(|filter| filter.is_match(content)),
+        };
+
+        let mut rooms: Vec<String> = self.rooms.iter().filter(matches).map(|room| room.name.clone()).collect();
+        if rooms.is_empty() {
+            rooms.push(DEFAULT_ROOM.to_string());
+        }
+        rooms
+    }
+
+    /// Sends a message to a single room's client; false if the room is unknown or its client has stopped
+    pub fn send(&self, room: &str, message: OutgoingMessage) -> bool {
+        self.room(room)
+            .is_some_and(|room| room.tx.send(message).is_ok())
+    }
+
+    /// Sends a message about a history item to the room it was shared in, or to every room
+    /// for local items shared with several
+    pub fn send_for_item(&self, item: &HistoryItem, message: OutgoingMessage) {
+        match item.room {
+            Some(ref room) => {
+                self.send(room, message);
+            }
+            None => {
+                for room in &self.rooms {
+                    let _ = room.tx.send(message.clone());
+                }
+            }
+        }
+    }
+
+    /// Shares `content` according to the routing rule and returns the rooms it was handed to
+    pub fn broadcast(&self, content: &str) -> Vec<String> {
+        self.route(content)
+            .into_iter()
+            .filter(|room| self.send(room, OutgoingMessage::broadcast(content.to_string())))
+            .collect()
+    }
+}
+
+/// The room a local item belongs to, or `None` when it was shared with several rooms
+pub fn local_room(rooms: &[String]) -> Option<String> {
+    match rooms {
+        [room] => Some(room.clone()),
+        _ => None,
+    }
+}
+
+/// Looks a device up by ID or (case-insensitive) name, returning the room it was seen in
+pub fn find_device(presence: &Presence, query: &str) -> Option<(String, DeviceInfo)> {
+    presence.iter().find_map(|(room, devices)| {
+        devices
+            .iter()
+            .find(|device| device.id == query || device.name.eq_ignore_ascii_case(query))
+            .map(|device| (room.clone(), device.clone()))
+    })
+}
@@ -0,0 +1,335 @@
+use crate::websocket::{DeviceInfo, HistoryData};
+use anyhow::{bail, Context, Result};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+// How long an incomplete transfer waits for its remaining chunks
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Size limits for clipboard content sent over the WebSocket
+#[derive(Debug, Clone, Copy)]
+pub struct TransferLimits {
+    /// Content larger than this is neither sent nor accepted (0 = unlimited)
+    pub max_bytes: usize,
+    /// Content longer than this once escaped for JSON is sent in chunks of at most this
+    /// escaped length (0 = never chunk)
+    pub chunk_bytes: usize,
+    /// Messages larger than this are compressed when the server supports it (0 = never compress)
+    pub compress_bytes: usize,
+}
+
+impl TransferLimits {
+    pub fn allows(&self, size: usize) -> bool {
+        self.max_bytes == 0 || size <= self.max_bytes
+    }
+
+    pub fn needs_chunks(&self, content: &str) -> bool {
+        self.chunk_bytes > 0 && json_len(content) > self.chunk_bytes
+    }
+
+    pub fn should_compress(&self, size: usize) -> bool {
+        self.compress_bytes > 0 && size > self.compress_bytes
+    }
+}
+
+/// Name of the compression scheme offered to and accepted from the server
+pub const COMPRESSION: &str = "deflate";
+
+/// Compresses a message for a binary frame. The zlib format is what the server's
+/// DecompressionStream calls "deflate".
+pub fn compress(text: &str) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(text.as_bytes())?;
+    Ok(encoder.finish()?)
+}
+
+/// Decompresses a binary frame, refusing output beyond `limit` bytes so a small frame
+/// can't expand without bound
+pub fn decompress(data: &[u8], limit: usize) -> Result<String> {
+    let mut text = String::new();
+    ZlibDecoder::new(data)
+        .take(limit as u64 + 1)
+        .read_to_string(&mut text)
+        .context("Invalid compressed message")?;
+    if text.len() > limit {
+        bail!("Compressed message expands beyond {} bytes", limit);
+    }
+    Ok(text)
+}
+
+/// One piece of a chunked clipboard update
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chunk {
+    /// Shared by all chunks of a transfer and used as the item's ID
+    pub transfer_id: String,
+    pub index: usize,
+    pub total: usize,
+    /// Size of the complete content in bytes
+    pub size: usize,
+    /// SHA-256 of the complete content, hex encoded
+    pub checksum: String,
+    pub chunk: String,
+    pub timestamp: i64,
+    #[serde(default)]
+    pub sensitive: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<DeviceInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+pub fn checksum(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+// Length of a character inside a JSON string; control characters without a short escape
+// grow sixfold, e.g. to \u0001
+fn escaped_len(ch: char) -> usize {
+    match ch {
+        '"' | '\\' | '\n' | '\r' | '\t' | '\u{8}' | '\u{c}' => 2,
+        '\0'..='\u{1f}' => 6,
+        _ => ch.len_utf8(),
+    }
+}
+
+/// Length of content once escaped as a JSON string, which is what it adds to a message
+pub fn json_len(content: &str) -> usize {
+    content.chars().map(escaped_len).sum()
+}
+
+/// Splits content into pieces whose JSON-escaped length is at most `chunk_bytes`, never
+/// inside a character
+pub fn split(content: &str, chunk_bytes: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut escaped = 0;
+    for (index, ch) in content.char_indices() {
+        let len = escaped_len(ch);
+        // A chunk size smaller than one character still has to make progress
+        if escaped + len > chunk_bytes && index > start {
+            pieces.push(&content[start..index]);
+            start = index;
+            escaped = 0;
+        }
+        escaped += len;
+    }
+    if start < content.len() {
+        pieces.push(&content[start..]);
+    }
+    pieces
+}
+
+/// Human-readable size for notifications, e.g. "12.3 MB"
+pub fn format_size(bytes: usize) -> String {
+    match bytes {
+        0..1_000 => format!("{} bytes", bytes),
+        1_000..1_000_000 => format!("{:.1} KB", bytes as f64 / 1_000.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_000_000.0),
+    }
+}
+
+struct Transfer {
+    total: usize,
+    chunks: BTreeMap<usize, String>,
+    bytes: usize,
+    started: Instant,
+}
+
+/// Collects chunks until a transfer is complete
+#[derive(Default)]
+pub struct Reassembler {
+    transfers: HashMap<String, Transfer>,
+}
+
+impl Reassembler {
+    /// Adds a chunk, returning the complete item once every chunk has arrived. Fails if the
+    /// chunks don't fit together or the content doesn't match its size and checksum.
+    pub fn add(&mut self, chunk: Chunk) -> Result<Option<HistoryData>> {
+        // Senders that disconnected mid-transfer never finish
+        self.transfers
+            .retain(|_, transfer| transfer.started.elapsed() < TRANSFER_TIMEOUT);
+
+        if chunk.index >= chunk.total {
+            bail!("Chunk {} of transfer {} is out of range", chunk.index, chunk.transfer_id);
+        }
+
+        let transfer = self
+            .transfers
+            .entry(chunk.transfer_id.clone())
+            .or_insert_with(|| Transfer {
+                total: chunk.total,
+                chunks: BTreeMap::new(),
+                bytes: 0,
+                started: Instant::now(),
+            });
+        if !transfer.chunks.contains_key(&chunk.index) {
+            transfer.bytes += chunk.chunk.len();
+            transfer.chunks.insert(chunk.index, chunk.chunk);
+        }
+        if transfer.total != chunk.total || transfer.bytes > chunk.size {
+            self.transfers.remove(&chunk.transfer_id);
+            bail!("Chunks of transfer {} don't fit together", chunk.transfer_id);
+        }
+        if transfer.chunks.len() < transfer.total {
+            return Ok(None);
+        }
+
+        let Some(transfer) = self.transfers.remove(&chunk.transfer_id) else {
+            return Ok(None);
+        };
+        let content: String = transfer.chunks.into_values().collect();
+        if content.len() != chunk.size || checksum(&content) != chunk.checksum {
+            bail!("Transfer {} failed its integrity check", chunk.transfer_id);
+        }
+
+        Ok(Some(HistoryData {
+            id: chunk.transfer_id,
+            content,
+            timestamp: chunk.timestamp,
+            sensitive: chunk.sensitive,
+            origin: chunk.origin,
+            device: chunk.device,
+            target: chunk.target,
+            pinned: false,
+        }))
+    }
+
+    /// Drops partial transfers, whose remaining chunks won't arrive on a new connection
+    pub fn clear(&mut self) {
+        self.transfers.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_sizes_chunks_by_escaped_length() {
+        let content = "ab\u{1}\"cd\ne";
+        let pieces = split(content, 7);
+
+        assert_eq!(pieces.concat(), content);
+        assert_eq!(pieces, ["ab", "\u{1}", "\"cd\ne"]);
+        assert!(pieces.iter().all(|piece| json_len(piece) <= 7));
+        assert_eq!(json_len("\u{1}"), serde_json::to_string("\u{1}").unwrap().len() - 2);
+    }
+
+    #[test]
+    fn split_never_cuts_a_character() {
+        let content = "ééé✓";
+
+        assert_eq!(split(content, 3), ["é", "é", "é", "✓"]);
+        assert_eq!(split(content, 1), ["é", "é", "é", "✓"]);
+    }
+
+    fn chunks(content: &str, chunk_bytes: usize) -> Vec<Chunk> {
+        let pieces = split(content, chunk_bytes);
+        let total = pieces.len();
+        pieces
+            .into_iter()
+            .enumerate()
+            .map(|(index, piece)| Chunk {
+                transfer_id: "transfer".to_string(),
+                index,
+                total,
+                size: content.len(),
+                checksum: checksum(content),
+                chunk: piece.to_string(),
+                timestamp: 1000,
+                sensitive: false,
+                origin: None,
+                device: None,
+                target: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn compression_round_trips() {
+        let text = "{\"type\":\"clipboard_update\",\"content\":\"ünïcödé\"}".repeat(50);
+
+        let compressed = compress(&text).unwrap();
+
+        assert!(compressed.len() < text.len());
+        assert_eq!(decompress(&compressed, text.len()).unwrap(), text);
+    }
+
+    #[test]
+    fn decompression_refuses_output_over_the_limit() {
+        let compressed = compress(&"x".repeat(10_000)).unwrap();
+
+        assert!(decompress(&compressed, 9_999).is_err());
+        assert!(decompress(b"not deflate", 100).is_err());
+    }
+
+    #[test]
+    fn chunks_reassemble_in_any_order() {
+        let content = "héllo ".repeat(20);
+        let mut reassembler = Reassembler::default();
+        let mut pieces = chunks(&content, 16);
+        pieces.reverse();
+        let last = pieces.pop().unwrap();
+
+        for chunk in pieces {
+            assert!(reassembler.add(chunk).unwrap().is_none());
+        }
+        let item = reassembler.add(last).unwrap().unwrap();
+
+        assert_eq!(item.content, content);
+        assert_eq!(item.id, "transfer");
+    }
+
+    #[test]
+    fn reassembly_rejects_a_checksum_mismatch() {
+        let mut reassembler = Reassembler::default();
+        let mut pieces = chunks("some content to send", 8);
+        for chunk in &mut pieces {
+            chunk.checksum = checksum("other content to send");
+        }
+        let last = pieces.pop().unwrap();
+
+        for chunk in pieces {
+            reassembler.add(chunk).unwrap();
+        }
+
+        assert!(reassembler.add(last).is_err());
+    }
+
+    #[test]
+    fn reassembly_rejects_chunks_that_do_not_fit() {
+        let mut reassembler = Reassembler::default();
+        let mut pieces = chunks("some content to send", 8);
+
+        let mut out_of_range = pieces[0].clone();
+        out_of_range.index = out_of_range.total;
+        assert!(reassembler.add(out_of_range).is_err());
+
+        // More content than the announced size
+        pieces[0].size = 4;
+        assert!(reassembler.add(pieces.remove(0)).is_err());
+    }
+
+    #[test]
+    fn limits_allow_and_chunk_by_size() {
+        let limits = TransferLimits {
+            max_bytes: 10,
+            chunk_bytes: 4,
+            compress_bytes: 0,
+        };
+
+        assert!(limits.allows(10));
+        assert!(!limits.allows(11));
+        assert!(!limits.needs_chunks("abcd"));
+        assert!(limits.needs_chunks("ab\n\t"));
+        assert!(!limits.should_compress(usize::MAX));
+    }
+}
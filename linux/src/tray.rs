@@ -1,32 +1,49 @@
-use crate::history::ClipboardHistory;
-use crate::{BROADCAST_DIALOG, SETTINGS_DIALOG, HELP_DIALOG, ABOUT_DIALOG, SHOW_HISTORY, extract_dialog};
-use ksni;
+use crate::clipboard::ClipboardManager;
+use crate::config::Config;
+use crate::heartbeat::format_latency;
+use crate::history::{ClipboardHistory, HistoryItem};
+use crate::notifications::NotificationAction;
+use crate::rooms::{local_room, Connections, Presence, Room, RoomRouter};
+use crate::websocket::{DeviceInfo, OutgoingMessage};
+use crate::{BROADCAST_DIALOG, SETTINGS_DIALOG, HELP_DIALOG, ABOUT_DIALOG, SHOW_HISTORY, SEARCH_HISTORY, CONFIRM_CLEAR_DIALOG, extract_dialog};
 use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
 use base64::{Engine as _, engine::general_purpose};
 
 pub struct TrayIcon {
     connected: Arc<Mutex<bool>>,
+    presence: Arc<Mutex<Presence>>,
+    connections: Arc<Mutex<Connections>>,
     history: Arc<Mutex<ClipboardHistory>>,
-    ws_tx: Option<Arc<Mutex<mpsc::UnboundedSender<String>>>>,
-    http_url: String,
-    token: String,
+    clipboard: Arc<ClipboardManager>,
+    rooms: Arc<RoomRouter>,
+    actions: mpsc::UnboundedSender<NotificationAction>,
+    confirm_clear: bool,
+    // Receive-only devices offer no way to send items
+    sends: bool,
     pub refresh_counter: Arc<Mutex<u64>>, // Force menu rebuild
 }
 
 impl TrayIcon {
     pub fn new(
         history: Arc<Mutex<ClipboardHistory>>,
-        ws_tx: Option<mpsc::UnboundedSender<String>>,
-        http_url: String,
-        token: String,
+        presence: Arc<Mutex<Presence>>,
+        connections: Arc<Mutex<Connections>>,
+        clipboard: Arc<ClipboardManager>,
+        rooms: Arc<RoomRouter>,
+        actions: mpsc::UnboundedSender<NotificationAction>,
+        config: &Config,
     ) -> Self {
         Self {
             connected: Arc::new(Mutex::new(false)),
+            presence,
+            connections,
             history,
-            ws_tx: ws_tx.map(|tx| Arc::new(Mutex::new(tx))),
-            http_url,
-            token,
+            clipboard,
+            rooms,
+            actions,
+            confirm_clear: config.clipboard.confirm_clear,
+            sends: config.device.direction.sends(),
             refresh_counter: Arc::new(Mutex::new(0)),
         }
     }
@@ -58,6 +75,318 @@ impl TrayIcon {
     }
 }
 
+impl TrayIcon {
+    // Sends content to a single device; other devices neither receive nor store it
+    fn send_to_device(&self, room: &str, device: &DeviceInfo, content: String) {
+        if !self.sends {
+            log::warn!("Receive-only device, not sending to {}", device.name);
+            return;
+        }
+        let message = OutgoingMessage::ClipboardUpdate {
+            content,
+            target: Some(device.id.clone()),
+        };
+        if !self.rooms.send(room, message) {
+            log::error!("Failed to send clipboard to {}", device.name);
+            return;
+        }
+        log::info!("Sent clipboard item to {}", device.name);
+    }
+
+    // The search dialog queries through the CLI and reports the chosen item as "COPY:<id>"
+    fn search_history(&self) {
+        use std::io::{BufRead, BufReader};
+        use std::process::Command;
+
+        let script_path = match extract_dialog(SEARCH_HISTORY, "search_history.py") {
+            Ok(path) => path,
+            Err(e) => {
+                log::error!("Failed to extract search dialog: {}", e);
+                return;
+            }
+        };
+        let corridor_path = match std::env::current_exe() {
+            Ok(path) => path,
+            Err(e) => {
+                log::error!("Failed to locate corridor executable: {}", e);
+                return;
+            }
+        };
+
+        let history = self.history.clone();
+        let clipboard = self.clipboard.clone();
+        std::thread::spawn(move || {
+            let Ok(mut child) = Command::new("python3")
+                .arg(&script_path)
+                .arg(&corridor_path)
+                .stdout(std::process::Stdio::piped())
+                .spawn()
+            else {
+                log::error!("Failed to start search dialog");
+                return;
+            };
+
+            if let Some(stdout) = child.stdout.take() {
+                for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                    let Some(id) = line.trim().strip_prefix("COPY:") else {
+                        continue;
+                    };
+                    let content = history.lock().ok().and_then(|hist| hist.get(id).map(|item| item.content.clone()));
+                    match content {
+                        Some(content) => {
+                            if let Err(e) = clipboard.set_text(content, None) {
+                                log::error!("Failed to copy search result: {}", e);
+                            }
+                        }
+                        None => log::warn!("Search result {} is no longer in history", id),
+                    }
+                }
+            }
+            let _ = child.wait();
+        });
+    }
+
+    fn send_to_device_menu(&self, room: &str, device: &DeviceInfo, recent_items: &[HistoryItem]) -> Vec<ksni::MenuItem<Self>> {
+        use ksni::menu::*;
+
+        let mut submenu: Vec<ksni::MenuItem<Self>> = Vec::new();
+
+        let target = device.clone();
+        let target_room = room.to_string();
+        submenu.push(
+            StandardItem {
+                label: "Send Current Clipboard".to_string(),
+                activate: Box::new(move |tray: &mut TrayIcon| {
+                    match tray.clipboard.get_text() {
+                        Ok(content) => tray.send_to_device(&target_room, &target, content),
+                        Err(e) => log::error!("Failed to read clipboard: {}", e),
+                    }
+                }),
+                ..Default::default()
+            }
+            .into(),
+        );
+
+        if !recent_items.is_empty() {
+            submenu.push(MenuItem::Separator);
+        }
+
+        for item in recent_items {
+            let target = device.clone();
+            let target_room = room.to_string();
+            let content = item.content.clone();
+            submenu.push(
+                StandardItem {
+                    label: format!("Send: {}", preview(item)),
+                    activate: Box::new(move |tray: &mut TrayIcon| {
+                        tray.send_to_device(&target_room, &target, content.clone());
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+
+        submenu
+    }
+
+    // Pins or unpins locally and on the server of the item's room, or every room for
+    // local items shared with several rooms
+    fn set_pinned(&self, item: &HistoryItem, pinned: bool) {
+        if let Ok(mut hist) = self.history.lock() {
+            hist.set_pinned(&item.id, &item.content, pinned);
+        }
+
+        let message = OutgoingMessage::Pin {
+            id: item.id.clone(),
+            content: item.content.clone(),
+            pinned,
+        };
+        self.rooms.send_for_item(item, message);
+
+        if let Ok(mut counter) = self.refresh_counter.lock() {
+            *counter = counter.wrapping_add(1);
+        }
+    }
+
+    // Deletes locally and asks the server to delete for every device in the room
+    fn delete_item(&self, id: &str) {
+        let removed = self.history.lock().ok().and_then(|mut hist| hist.remove(id));
+        if let Some(item) = removed {
+            let message = OutgoingMessage::Delete {
+                id: item.id.clone(),
+                content: item.content.clone(),
+            };
+            self.rooms.send_for_item(&item, message);
+        }
+
+        if let Ok(mut counter) = self.refresh_counter.lock() {
+            *counter = counter.wrapping_add(1);
+        }
+    }
+
+    // The viewer shows a single room's server history, so with several rooms pick one
+    fn view_history_item(&self) -> ksni::MenuItem<Self> {
+        use ksni::menu::*;
+
+        if !self.rooms.is_multi_room() {
+            return StandardItem {
+                label: "View Full History".to_string(),
+                activate: Box::new(|tray: &mut TrayIcon| show_history(tray.rooms.default_room())),
+                ..Default::default()
+            }
+            .into();
+        }
+
+        SubMenu {
+            label: "View Full History".to_string(),
+            submenu: self
+                .rooms
+                .rooms()
+                .iter()
+                .map(|room| {
+                    let name = room.name.clone();
+                    StandardItem {
+                        label: room.name.clone(),
+                        activate: Box::new(move |tray: &mut TrayIcon| {
+                            if let Some(room) = tray.rooms.room(&name) {
+                                show_history(room);
+                            }
+                        }),
+                        ..Default::default()
+                    }
+                    .into()
+                })
+                .collect(),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    fn delete_menu(&self, items: &[HistoryItem]) -> Vec<ksni::MenuItem<Self>> {
+        use ksni::menu::*;
+
+        if items.is_empty() {
+            return vec![StandardItem {
+                label: "No history available".to_string(),
+                enabled: false,
+                ..Default::default()
+            }
+            .into()];
+        }
+
+        items
+            .iter()
+            .map(|item| {
+                let id = item.id.clone();
+                StandardItem {
+                    label: preview(item),
+                    activate: Box::new(move |tray: &mut TrayIcon| tray.delete_item(&id)),
+                    ..Default::default()
+                }
+                .into()
+            })
+            .collect()
+    }
+
+    fn pin_menu(&self, items: &[&HistoryItem], pinned: bool) -> Vec<ksni::MenuItem<Self>> {
+        use ksni::menu::*;
+
+        if items.is_empty() {
+            return vec![StandardItem {
+                label: if pinned { "No recent items to pin" } else { "No pinned items" }.to_string(),
+                enabled: false,
+                ..Default::default()
+            }
+            .into()];
+        }
+
+        items
+            .iter()
+            .map(|item| {
+                let item = (*item).clone();
+                StandardItem {
+                    label: preview(&item),
+                    activate: Box::new(move |tray: &mut TrayIcon| tray.set_pinned(&item, pinned)),
+                    ..Default::default()
+                }
+                .into()
+            })
+            .collect()
+    }
+
+    // The clear itself happens in the main loop, which owns the undo notification
+    fn clear_history(&self, include_pinned: bool) {
+        use std::process::Command;
+
+        let action = NotificationAction::ClearHistory { include_pinned };
+        if !self.confirm_clear {
+            let _ = self.actions.send(action);
+            return;
+        }
+
+        let script_path = match extract_dialog(CONFIRM_CLEAR_DIALOG, "confirm_clear_dialog.py") {
+            Ok(path) => path,
+            Err(e) => {
+                log::error!("Failed to extract confirmation dialog: {}", e);
+                return;
+            }
+        };
+
+        let actions = self.actions.clone();
+        std::thread::spawn(move || {
+            let mut command = Command::new("python3");
+            command.arg(&script_path);
+            if include_pinned {
+                command.arg("--pinned");
+            }
+            if let Ok(output) = command.output() {
+                if String::from_utf8_lossy(&output.stdout).trim() == "clear" {
+                    let _ = actions.send(action);
+                }
+            }
+        });
+    }
+}
+
+/// Opens the full history viewer for a room's server history
+pub fn show_history(room: &Room) {
+    use std::process::Command;
+
+    let http_url = room.http_url.clone();
+    let token = room.token.clone();
+
+    std::thread::spawn(move || {
+        let script_path = match extract_dialog(SHOW_HISTORY, "show_history.py") {
+            Ok(path) => path,
+            Err(e) => {
+                log::error!("Failed to extract show_history dialog: {}", e);
+                return;
+            }
+        };
+
+        // Pass "fetch" as first arg to fetch from server
+        let _ = Command::new("python3")
+            .arg(&script_path)
+            .arg("fetch")
+            .arg(&http_url)
+            .arg(&token)
+            .spawn();
+    });
+}
+
+// Single-line menu label for a history item
+fn preview(item: &HistoryItem) -> String {
+    let single_line = item.content.lines().next().unwrap_or("").chars().take(80).collect::<String>();
+    if item.sensitive {
+        "🔒 Sensitive item".to_string()
+    } else if item.content.len() > 80 || item.content.contains('\n') {
+        format!("{}...", single_line)
+    } else {
+        single_line
+    }
+}
+
 impl ksni::Tray for TrayIcon {
     fn icon_name(&self) -> String {
         String::new()
@@ -157,10 +486,16 @@ impl ksni::Tray for TrayIcon {
         // 1. Status (non-clickable)
         let pending_count = history.pending_sync_count();
         let status_label = if connected {
+            // With several rooms the slowest server is the one worth knowing about
+            let latency = self.connections.lock().unwrap().values().flatten().max().copied();
+            let connected_label = match latency {
+                Some(latency) => format!("Status: ✓ Connected · {}", format_latency(latency)),
+                None => "Status: ✓ Connected".to_string(),
+            };
             if pending_count > 0 {
-                format!("Status: ✓ Connected (Syncing {} items...)", pending_count)
+                format!("{} (Syncing {} items...)", connected_label, pending_count)
             } else {
-                "Status: ✓ Connected".to_string()
+                connected_label
             }
         } else {
             if pending_count > 0 {
@@ -179,10 +514,62 @@ impl ksni::Tray for TrayIcon {
             .into(),
         );
 
+        // Other devices currently in our rooms
+        if connected {
+            let presence = self.presence.lock().unwrap();
+            let devices: Vec<(&String, &DeviceInfo)> = presence
+                .iter()
+                .flat_map(|(room, devices)| devices.iter().map(move |device| (room, device)))
+                .collect();
+            let submenu: Vec<ksni::MenuItem<Self>> = if devices.is_empty() {
+                vec![StandardItem {
+                    label: "No other devices online".to_string(),
+                    enabled: false,
+                    ..Default::default()
+                }
+                .into()]
+            } else {
+                devices
+                    .iter()
+                    .map(|(room, device)| {
+                        let label = if self.rooms.is_multi_room() {
+                            format!("{} ({})", device.name, room)
+                        } else {
+                            device.name.clone()
+                        };
+                        if !self.sends {
+                            return StandardItem {
+                                label,
+                                enabled: false,
+                                ..Default::default()
+                            }
+                            .into();
+                        }
+                        SubMenu {
+                            label,
+                            submenu: self.send_to_device_menu(room, device, recent_items),
+                            ..Default::default()
+                        }
+                        .into()
+                    })
+                    .collect()
+            };
+
+            menu.push(
+                SubMenu {
+                    label: format!("Devices Online ({})", devices.len()),
+                    submenu,
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+
         // 2. Clipboard Broadcast
         menu.push(
             StandardItem {
                 label: "Clipboard Broadcast...".to_string(),
+                visible: self.sends,
                 activate: Box::new(|tray: &mut TrayIcon| {
                     use std::process::Command;
                     use std::io::{BufRead, BufReader};
@@ -196,7 +583,7 @@ impl ksni::Tray for TrayIcon {
                         }
                     };
 
-                    let ws_tx_clone = tray.ws_tx.clone();
+                    let rooms_clone = tray.rooms.clone();
                     let history_clone = tray.history.clone();
                     let refresh_counter_clone = tray.refresh_counter.clone();
                     std::thread::spawn(move || {
@@ -208,32 +595,26 @@ impl ksni::Tray for TrayIcon {
                         {
                             if let Some(stdout) = child.stdout.take() {
                                 let reader = BufReader::new(stdout);
-                                for line in reader.lines() {
-                                    if let Ok(line_text) = line {
-                                        let line_text = line_text.trim();
-                                        // Check for SYNC: prefix with base64 encoded content
-                                        if line_text.starts_with("SYNC:") {
-                                            if let Some(encoded) = line_text.strip_prefix("SYNC:") {
-                                                // Decode base64 to get full text
-                                                if let Ok(decoded_bytes) = general_purpose::STANDARD.decode(encoded) {
-                                                    if let Ok(text) = String::from_utf8(decoded_bytes) {
-                                                        if !text.is_empty() {
-                                                            // Add to local history
-                                                            if let Ok(mut hist) = history_clone.lock() {
-                                                                hist.add_local(text.clone());
-                                                            }
-
-                                                            // Send to WebSocket
-                                                            if let Some(ws_tx) = &ws_tx_clone {
-                                                                if let Ok(tx) = ws_tx.lock() {
-                                                                    let _ = tx.send(text);
-                                                                }
-                                                            }
-
-                                                            // Force tray refresh (increment counter in background)
-                                                            if let Ok(mut counter) = refresh_counter_clone.lock() {
-                                                                *counter = counter.wrapping_add(1);
-                                                            }
+                                for line_text in reader.lines().map_while(Result::ok) {
+                                    let line_text = line_text.trim();
+                                    // Check for SYNC: prefix with base64 encoded content
+                                    if line_text.starts_with("SYNC:") {
+                                        if let Some(encoded) = line_text.strip_prefix("SYNC:") {
+                                            // Decode base64 to get full text
+                                            if let Ok(decoded_bytes) = general_purpose::STANDARD.decode(encoded) {
+                                                if let Ok(text) = String::from_utf8(decoded_bytes) {
+                                                    if !text.is_empty() {
+                                                        // Send to the rooms chosen by the routing rule
+                                                        let rooms = rooms_clone.broadcast(&text);
+
+                                                        // Add to local history
+                                                        if let Ok(mut hist) = history_clone.lock() {
+                                                            hist.add_local(text, local_room(&rooms));
+                                                        }
+
+                                                        // Force tray refresh (increment counter in background)
+                                                        if let Ok(mut counter) = refresh_counter_clone.lock() {
+                                                            *counter = counter.wrapping_add(1);
                                                         }
                                                     }
                                                 }
@@ -332,7 +713,37 @@ impl ksni::Tray for TrayIcon {
 
         menu.push(MenuItem::Separator);
 
-        // 4. Recent Clipboard
+        // 4. Pinned items, only shown once something is pinned
+        let pinned_items = history.pinned();
+        if !pinned_items.is_empty() {
+            menu.push(
+                StandardItem {
+                    label: "Pinned:".to_string(),
+                    enabled: false,
+                    ..Default::default()
+                }
+                .into(),
+            );
+            for item in &pinned_items {
+                let content_copy = item.content.clone();
+                menu.push(
+                    StandardItem {
+                        label: format!("📌 {}", preview(item)),
+                        activate: Box::new(move |tray: &mut TrayIcon| {
+                            if let Err(e) = tray.clipboard.set_text(content_copy.clone(), None) {
+                                log::error!("Failed to copy pinned item: {}", e);
+                            }
+                        }),
+                        ..Default::default()
+                    }
+                    .into(),
+                );
+            }
+            menu.push(MenuItem::Separator);
+        }
+        let unpinned_recent: Vec<&HistoryItem> = recent_items.iter().filter(|item| !item.pinned).collect();
+
+        // 5. Recent Clipboard
         menu.push(
             StandardItem {
                 label: "Recent Clipboard:".to_string(),
@@ -344,20 +755,32 @@ impl ksni::Tray for TrayIcon {
 
         if !recent_items.is_empty() {
             for item in recent_items {
-                let single_line = item.content.lines().next().unwrap_or("").chars().take(80).collect::<String>();
-                let preview = if item.content.len() > 80 || item.content.contains('\n') {
-                    format!("{}...", single_line)
-                } else {
-                    single_line
+                // Items waiting in manual-accept mode stand out and are applied on click
+                let icon = if item.pending { "⏳" } else { "🗐" };
+                let mut label = match item.device_name {
+                    Some(ref device_name) => format!("{} {}  (from {})", icon, preview(item), device_name),
+                    None => format!("{} {}", icon, preview(item)),
                 };
+                if item.copy_count > 1 {
+                    label.push_str(&format!("  ×{}", item.copy_count));
+                }
+                if let (true, Some(room)) = (self.rooms.is_multi_room(), &item.room) {
+                    label.push_str(&format!("  [{}]", room));
+                }
+                if item.pending {
+                    label.push_str("  • not applied");
+                }
 
                 let content_copy = item.content.clone();
+                let pending_id = item.pending.then(|| item.id.clone());
                 menu.push(
                     StandardItem {
-                        label: format!("🗐 {}", preview),
-                        activate: Box::new(move |_tray: &mut TrayIcon| {
-                            if let Ok(mut clipboard) = arboard::Clipboard::new() {
-                                let _ = clipboard.set_text(&content_copy);
+                        label,
+                        activate: Box::new(move |tray: &mut TrayIcon| {
+                            if let Some(ref id) = pending_id {
+                                let _ = tray.actions.send(NotificationAction::Apply(id.clone()));
+                            } else if let Err(e) = tray.clipboard.set_text(content_copy.clone(), None) {
+                                log::error!("Failed to copy history item: {}", e);
                             }
                         }),
                         ..Default::default()
@@ -365,6 +788,36 @@ impl ksni::Tray for TrayIcon {
                     .into(),
                 );
             }
+
+            // Older waiting items would otherwise be out of reach until newer ones are handled
+            let more_pending: Vec<&HistoryItem> = history
+                .pending()
+                .into_iter()
+                .filter(|item| !recent_items.iter().any(|recent| recent.id == item.id))
+                .collect();
+            if !more_pending.is_empty() {
+                menu.push(
+                    SubMenu {
+                        label: format!("⏳ {} more pending", more_pending.len()),
+                        submenu: more_pending
+                            .iter()
+                            .map(|item| {
+                                let id = item.id.clone();
+                                StandardItem {
+                                    label: preview(item),
+                                    activate: Box::new(move |tray: &mut TrayIcon| {
+                                        let _ = tray.actions.send(NotificationAction::Apply(id.clone()));
+                                    }),
+                                    ..Default::default()
+                                }
+                                .into()
+                            })
+                            .collect(),
+                        ..Default::default()
+                    }
+                    .into(),
+                );
+            }
         } else {
             menu.push(
                 StandardItem {
@@ -376,67 +829,59 @@ impl ksni::Tray for TrayIcon {
             );
         }
 
+        // Offered until the undo window of a clear from the tray runs out
+        if history.can_undo_clear() {
+            menu.push(
+                StandardItem {
+                    label: "↶ Undo Clear History".to_string(),
+                    activate: Box::new(|tray: &mut TrayIcon| {
+                        let _ = tray.actions.send(NotificationAction::UndoClear);
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+
         // History submenu (with chevron ⮞)
         menu.push(
             SubMenu {
                 label: "Manage History".to_string(),
                 submenu: vec![
                         StandardItem {
-                            label: "View Full History".to_string(),
-                            activate: Box::new(|tray: &mut TrayIcon| {
-                                use std::process::Command;
-
-                                let http_url = tray.http_url.clone();
-                                let token = tray.token.clone();
-
-                                std::thread::spawn(move || {
-                                    let script_path = match extract_dialog(SHOW_HISTORY, "show_history.py") {
-                                        Ok(path) => path,
-                                        Err(e) => {
-                                            log::error!("Failed to extract show_history dialog: {}", e);
-                                            return;
-                                        }
-                                    };
-
-                                    // Pass "fetch" as first arg to fetch from server
-                                    let _ = Command::new("python3")
-                                        .arg(&script_path)
-                                        .arg("fetch")
-                                        .arg(&http_url)
-                                        .arg(&token)
-                                        .spawn();
-                                });
-                            }),
+                            label: "Search History...".to_string(),
+                            activate: Box::new(|tray: &mut TrayIcon| tray.search_history()),
+                            ..Default::default()
+                        }
+                        .into(),
+                        self.view_history_item(),
+                        SubMenu {
+                            label: "Pin Item".to_string(),
+                            submenu: self.pin_menu(&unpinned_recent, true),
+                            ..Default::default()
+                        }
+                        .into(),
+                        SubMenu {
+                            label: "Unpin Item".to_string(),
+                            submenu: self.pin_menu(&pinned_items, false),
+                            ..Default::default()
+                        }
+                        .into(),
+                        SubMenu {
+                            label: "Delete Item".to_string(),
+                            submenu: self.delete_menu(recent_items),
                             ..Default::default()
                         }
                         .into(),
                         StandardItem {
                             label: "Clear History".to_string(),
-                            activate: Box::new(|tray: &mut TrayIcon| {
-                                use std::process::Command;
-
-                                // Clear local history immediately
-                                if let Ok(mut hist) = tray.history.lock() {
-                                    hist.clear();
-                                }
-
-                                // Force tray refresh immediately
-                                if let Ok(mut counter) = tray.refresh_counter.lock() {
-                                    *counter = counter.wrapping_add(1);
-                                }
-
-                                // Clear on server - server will broadcast clear_history to all clients
-                                let url = format!("{}/clipboard/{}", tray.http_url, tray.token);
-                                std::thread::spawn(move || {
-                                    let _ = Command::new("curl").args(&["-X", "DELETE", &url]).output();
-                                });
-
-                                let _ = notify_rust::Notification::new()
-                                    .summary("Corridor")
-                                    .body("History cleared")
-                                    .timeout(2000)
-                                    .show();
-                            }),
+                            activate: Box::new(|tray: &mut TrayIcon| tray.clear_history(false)),
+                            ..Default::default()
+                        }
+                        .into(),
+                        StandardItem {
+                            label: "Clear History (Including Pinned)".to_string(),
+                            activate: Box::new(|tray: &mut TrayIcon| tray.clear_history(true)),
                             ..Default::default()
                         }
                         .into(),
@@ -448,7 +893,7 @@ impl ksni::Tray for TrayIcon {
 
         menu.push(MenuItem::Separator);
 
-        // 5. Quit
+        // 6. Quit
         menu.push(
             StandardItem {
                 label: "Quit".to_string(),
@@ -1,45 +1,165 @@
+use crate::heartbeat::Heartbeat;
+use crate::history::HistoryItem;
+use crate::transfer::{self, Chunk, Reassembler, TransferLimits};
 use anyhow::{Context, Result};
 use chrono::Utc;
 use futures_util::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::VecDeque;
 use tokio::sync::mpsc;
-use tokio::time::{interval, Duration};
+use tokio::time::{Duration, Instant};
 use tokio_tungstenite::{connect_async, tungstenite::Message};
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    pub id: String,
+    pub name: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryData {
     pub id: String,
     pub content: String,
     pub timestamp: i64,
+    #[serde(default)]
+    pub sensitive: bool,
+    /// Unique ID the sending client attached to the update
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    /// Device that copied the item
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<DeviceInfo>,
+    /// Set when the item was sent to this device only and is not in the room's history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+impl HistoryData {
+    pub fn to_history_item(&self) -> HistoryItem {
+        let mut item = HistoryItem::from_remote(self.id.clone(), self.content.clone(), self.timestamp);
+        item.sensitive = self.sensitive;
+        item.device_name = self.device.as_ref().map(|device| device.name.clone());
+        item.pinned = self.pinned;
+        item
+    }
+
+    /// The server's view of a history item, for putting it back after a clear
+    pub fn from_history_item(item: &HistoryItem) -> Self {
+        Self {
+            id: item.id.clone(),
+            content: item.content.clone(),
+            timestamp: item.timestamp,
+            sensitive: item.sensitive,
+            origin: None,
+            device: None,
+            target: None,
+            pinned: item.pinned,
+        }
+    }
+}
+
+// Number of our own recent update origins remembered to recognize echoes
+const SENT_ORIGINS_CAPACITY: usize = 100;
+
+// Largest message a compressed frame may expand to
+const MAX_DECOMPRESSED_BYTES: usize = 64 * 1024 * 1024;
+
+/// Messages the rest of the app asks the WebSocket client to send
+#[derive(Debug, Clone)]
+pub enum OutgoingMessage {
+    /// Share clipboard content, either with every device or only with `target` (a device ID)
+    ClipboardUpdate {
+        content: String,
+        target: Option<String>,
+    },
+    /// Pin or unpin a history item on the server; `content` identifies items the server
+    /// knows under a different ID
+    Pin {
+        id: String,
+        content: String,
+        pinned: bool,
+    },
+    /// Delete a history item on the server and every other device, matched like `Pin`
+    Delete { id: String, content: String },
+    /// Clear the room's history on the server and every device
+    ClearHistory { include_pinned: bool },
+    /// Put items removed by a clear back into the room's history
+    RestoreHistory(Vec<HistoryData>),
+}
+
+impl OutgoingMessage {
+    pub fn broadcast(content: String) -> Self {
+        Self::ClipboardUpdate {
+            content,
+            target: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum WsEvent {
     Connected,
     Disconnected,
-    ClipboardUpdate(String),
+    ClipboardUpdate(HistoryData),
+    /// Items newer than our cursor, or the full history on first connect
     ClipboardHistory(Vec<HistoryData>),
-    ClearHistory,
+    /// The server no longer has our cursor, so this is its complete history
+    HistoryResync(Vec<HistoryData>),
+    ClearHistory { include_pinned: bool },
+    /// Another device pinned or unpinned an item
+    Pin { id: String, content: String, pinned: bool },
+    /// Another device deleted an item
+    DeleteItem { id: String, content: String },
+    /// An item wasn't sent (or an incoming one was skipped) for being over the size limit
+    TooLarge { size: usize, limit: usize, incoming: bool },
+    Presence(Vec<DeviceInfo>),
+    /// Smoothed round-trip time to the server, updated on every heartbeat
+    Latency(Duration),
     Error(String),
 }
 
 pub struct WebSocketClient {
     token: String,
     url: String,
+    device: DeviceInfo,
+    sent_origins: VecDeque<String>,
+    // ID of the newest item known to be in the room's history, for incremental history sync
+    cursor: Option<String>,
+    // Set while an incremental history request is unanswered, so a full history the server
+    // pushes on its own in the meantime isn't merged on top of it
+    awaiting_since: bool,
+    limits: TransferLimits,
+    reassembler: Reassembler,
+    // Whether the server accepted compressed frames on the current connection
+    compression: bool,
+    // Whether the server reassembles chunked items on the current connection
+    chunking: bool,
+    heartbeat: Heartbeat,
 }
 
 impl WebSocketClient {
-    pub fn new(token: String, websocket_url: String) -> Self {
+    pub fn new(token: String, websocket_url: String, device: DeviceInfo, limits: TransferLimits) -> Self {
         Self {
             token,
             url: websocket_url,
+            device,
+            sent_origins: VecDeque::new(),
+            cursor: None,
+            awaiting_since: false,
+            limits,
+            reassembler: Reassembler::default(),
+            compression: false,
+            chunking: false,
+            heartbeat: Heartbeat::default(),
         }
     }
 
     pub async fn connect_and_run(
-        self,
-        mut rx: mpsc::UnboundedReceiver<String>,
+        mut self,
+        mut rx: mpsc::UnboundedReceiver<OutgoingMessage>,
         tx: mpsc::UnboundedSender<WsEvent>,
     ) -> Result<()> {
         loop {
@@ -60,11 +180,12 @@ impl WebSocketClient {
     }
 
     async fn connect_once(
-        &self,
-        rx: &mut mpsc::UnboundedReceiver<String>,
+        &mut self,
+        rx: &mut mpsc::UnboundedReceiver<OutgoingMessage>,
         tx: &mpsc::UnboundedSender<WsEvent>,
     ) -> Result<()> {
-        let url = format!("{}?token={}", self.url, self.token);
+        // We request history ourselves, so the server needn't push its full history on connect
+        let url = format!("{}?token={}&history=request", self.url, self.token);
         log::info!("Connecting to WebSocket: {}", self.url);
 
         let (ws_stream, _) = connect_async(&url)
@@ -73,27 +194,54 @@ impl WebSocketClient {
 
         log::info!("✓ WebSocket connected");
         let _ = tx.send(WsEvent::Connected);
+        self.reassembler.clear();
+        // Plain text and whole items until this server says it handles compression and chunks
+        self.compression = false;
+        self.chunking = false;
+        self.heartbeat = Heartbeat::default();
 
         let (mut write, mut read) = ws_stream.split();
 
-        // Request history on connect
-        let request_history = json!({
+        // Announce who we are so the room can attribute items and track presence
+        let mut announce = json!({
+            "type": "connect",
+            "device": self.device
+        });
+        if self.limits.compress_bytes > 0 {
+            announce["compression"] = json!([transfer::COMPRESSION]);
+        }
+        if self.limits.chunk_bytes > 0 {
+            announce["chunking"] = json!(true);
+        }
+        if let Err(e) = write.send(Message::Text(announce.to_string())).await {
+            log::error!("Failed to announce device: {}", e);
+        }
+
+        // Request history on connect, only what we missed if we've synced before
+        let mut request_history = json!({
             "type": "clipboard_history"
         });
+        if let Some(ref cursor) = self.cursor {
+            request_history["since"] = json!(cursor);
+        }
+        self.awaiting_since = false;
         if let Err(e) = write.send(Message::Text(request_history.to_string())).await {
             log::error!("Failed to request history: {}", e);
+        } else if self.cursor.is_some() {
+            self.awaiting_since = true;
+            log::info!("Requested clipboard history since last seen item");
         } else {
             log::info!("Requested clipboard history from server");
         }
 
-        // Use 5-second ping interval for faster disconnection detection
-        let mut ping_interval = interval(Duration::from_secs(5));
-        // If we don't get any response (pong or any message) within 10 seconds, disconnect
-        let mut last_response = tokio::time::Instant::now();
-        let connection_timeout = Duration::from_secs(10);
+        // Ping right away so latency is known soon after connecting; the interval and the
+        // timeout then follow the latency the heartbeat measures
+        let mut next_ping = Instant::now();
+        let mut last_response = Instant::now();
 
         loop {
-            // Check if connection is dead (no response in 10 seconds)
+            // Check if connection is dead (no message of any kind within the timeout)
+            let connection_timeout = self.heartbeat.timeout();
             if last_response.elapsed() > connection_timeout {
                 log::error!("Connection timeout - no response in {:?}", connection_timeout);
                 break;
@@ -103,19 +251,26 @@ impl WebSocketClient {
                 msg = read.next() => {
                     match msg {
                         Some(Ok(Message::Text(text))) => {
-                            last_response = tokio::time::Instant::now();
+                            last_response = Instant::now();
                             self.handle_message(&text, tx)?;
                         }
+                        Some(Ok(Message::Binary(data))) => {
+                            last_response = Instant::now();
+                            match transfer::decompress(&data, MAX_DECOMPRESSED_BYTES) {
+                                Ok(text) => self.handle_message(&text, tx)?,
+                                Err(e) => log::warn!("Ignoring binary message: {:#}", e),
+                            }
+                        }
                         Some(Ok(Message::Close(_))) => {
                             log::info!("WebSocket closed by server");
                             break;
                         }
                         Some(Ok(Message::Ping(_))) => {
-                            last_response = tokio::time::Instant::now();
+                            last_response = Instant::now();
                             log::debug!("Received ping");
                         }
                         Some(Ok(Message::Pong(_))) => {
-                            last_response = tokio::time::Instant::now();
+                            last_response = Instant::now();
                             log::debug!("Received pong - connection alive");
                         }
                         Some(Err(e)) => {
@@ -130,30 +285,116 @@ impl WebSocketClient {
                     }
                 }
 
-                content = rx.recv() => {
-                    if let Some(content) = content {
+                message = rx.recv() => match message {
+                    Some(OutgoingMessage::ClipboardUpdate { content, .. }) if !self.limits.allows(content.len()) => {
+                        log::warn!("Not sending clipboard update of {} bytes, over the size limit", content.len());
+                        let _ = tx.send(WsEvent::TooLarge {
+                            size: content.len(),
+                            limit: self.limits.max_bytes,
+                            incoming: false,
+                        });
+                    }
+                    Some(OutgoingMessage::ClipboardUpdate { content, target }) => {
+                        let origin = uuid::Uuid::new_v4().to_string();
+                        let messages = self.update_messages(&content, target.as_ref(), &origin);
+
+                        self.sent_origins.push_back(origin);
+                        if self.sent_origins.len() > SENT_ORIGINS_CAPACITY {
+                            self.sent_origins.pop_front();
+                        }
+
+                        let chunks = messages.len();
+                        let mut failed = false;
+                        for msg in messages {
+                            if let Err(e) = write.send(self.frame(msg)).await {
+                                log::error!("Failed to send clipboard update: {}", e);
+                                failed = true;
+                                break;
+                            }
+                        }
+                        if failed {
+                            break;
+                        }
+                        if chunks > 1 {
+                            log::info!("Sent {} bytes in {} chunks", content.len(), chunks);
+                        }
+                        match target {
+                            Some(target) => log::info!("✓ Sent clipboard update to device {}", target),
+                            None => log::info!("✓ Sent clipboard update"),
+                        }
+                    }
+                    Some(OutgoingMessage::Pin { id, content, pinned }) => {
+                        let msg = json!({
+                            "type": "pin",
+                            "data": {
+                                "id": id,
+                                "content": content,
+                                "timestamp": Utc::now().timestamp_millis(),
+                                "pinned": pinned
+                            }
+                        });
+                        if let Err(e) = write.send(Message::Text(msg.to_string())).await {
+                            log::error!("Failed to send pin: {}", e);
+                            break;
+                        }
+                        log::info!("✓ Sent {} for item {}", if pinned { "pin" } else { "unpin" }, id);
+                    }
+                    Some(OutgoingMessage::Delete { id, content }) => {
                         let msg = json!({
-                            "type": "clipboard_update",
+                            "type": "delete_item",
                             "data": {
+                                "id": id,
                                 "content": content,
                                 "timestamp": Utc::now().timestamp_millis()
                             }
                         });
-
                         if let Err(e) = write.send(Message::Text(msg.to_string())).await {
-                            log::error!("Failed to send clipboard update: {}", e);
+                            log::error!("Failed to send delete: {}", e);
                             break;
                         }
-                        log::info!("✓ Sent clipboard update");
+                        log::info!("✓ Sent delete for item {}", id);
                     }
-                }
+                    Some(OutgoingMessage::ClearHistory { include_pinned }) => {
+                        let msg = json!({
+                            "type": "clear_history",
+                            "include_pinned": include_pinned
+                        });
+                        if let Err(e) = write.send(Message::Text(msg.to_string())).await {
+                            log::error!("Failed to send clear history: {}", e);
+                            break;
+                        }
+                        log::info!("✓ Sent clear history");
+                    }
+                    Some(OutgoingMessage::RestoreHistory(items)) => {
+                        let messages = self.restore_messages(items);
+                        let count: usize = messages.iter().map(|(count, _)| count).sum();
+                        let mut failed = false;
+                        for (_, msg) in messages {
+                            if let Err(e) = write.send(self.frame(msg)).await {
+                                log::error!("Failed to send restored history: {}", e);
+                                failed = true;
+                                break;
+                            }
+                        }
+                        if failed {
+                            break;
+                        }
+                        log::info!("✓ Re-uploaded {} history items", count);
+                    }
+                    None => {}
+                },
 
-                _ = ping_interval.tick() => {
+                _ = tokio::time::sleep_until(next_ping) => {
                     log::debug!("Sending ping to check connection");
-                    if let Err(e) = write.send(Message::Ping(vec![])).await {
+                    let ping = json!({
+                        "type": "ping",
+                        "seq": self.heartbeat.ping()
+                    });
+                    if let Err(e) = write.send(Message::Text(ping.to_string())).await {
                         log::error!("Failed to send ping: {}", e);
                         break;
                     }
+                    next_ping = Instant::now() + self.heartbeat.interval();
                 }
 
                 _ = tokio::time::sleep(Duration::from_millis(100)) => {
@@ -165,33 +406,270 @@ impl WebSocketClient {
         Ok(())
     }
 
-    fn handle_message(&self, text: &str, tx: &mpsc::UnboundedSender<WsEvent>) -> Result<()> {
+    // Large payloads go out deflated in a binary frame once the server has said it accepts them
+    fn frame(&self, text: String) -> Message {
+        if !self.compression || !self.limits.should_compress(text.len()) {
+            return Message::Text(text);
+        }
+        match transfer::compress(&text) {
+            Ok(data) => {
+                log::debug!("Compressed {} bytes to {}", text.len(), data.len());
+                Message::Binary(data)
+            }
+            Err(e) => {
+                log::warn!("Compression failed, sending plain text: {}", e);
+                Message::Text(text)
+            }
+        }
+    }
+
+    // Content above the chunk size goes out as clipboard_chunk messages so no single frame
+    // gets too large for the server, if the server reassembles them
+    fn update_messages(&self, content: &str, target: Option<&String>, origin: &str) -> Vec<String> {
+        let timestamp = Utc::now().timestamp_millis();
+
+        let chunked = self.limits.needs_chunks(content);
+        if chunked && !self.chunking {
+            log::warn!("Server doesn't accept chunked items, sending {} bytes in one message", content.len());
+        }
+        if !chunked || !self.chunking {
+            let mut msg = json!({
+                "type": "clipboard_update",
+                "data": {
+                    "content": content,
+                    "timestamp": timestamp,
+                    "origin": origin,
+                    "device": self.device
+                }
+            });
+            if let Some(target) = target {
+                msg["data"]["target"] = json!(target);
+            }
+            return vec![msg.to_string()];
+        }
+
+        let transfer_id = uuid::Uuid::new_v4().to_string();
+        let checksum = transfer::checksum(content);
+        let pieces = transfer::split(content, self.limits.chunk_bytes);
+        let total = pieces.len();
+        pieces
+            .into_iter()
+            .enumerate()
+            .map(|(index, piece)| {
+                let chunk = Chunk {
+                    transfer_id: transfer_id.clone(),
+                    index,
+                    total,
+                    size: content.len(),
+                    checksum: checksum.clone(),
+                    chunk: piece.to_string(),
+                    timestamp,
+                    sensitive: false,
+                    origin: Some(origin.to_string()),
+                    device: Some(self.device.clone()),
+                    target: target.cloned(),
+                };
+                json!({ "type": "clipboard_chunk", "chunk": chunk }).to_string()
+            })
+            .collect()
+    }
+
+    // Restored items are sent in batches no larger than a chunk, each with the number of
+    // items it carries. Items too large for the sync limit or for a message of their own
+    // stay on this device only.
+    fn restore_messages(&self, items: Vec<HistoryData>) -> Vec<(usize, String)> {
+        let message = |batch: &[HistoryData]| {
+            json!({
+                "type": "restore_history",
+                "history": batch
+            })
+            .to_string()
+        };
+
+        let mut messages = Vec::new();
+        let mut batch: Vec<HistoryData> = Vec::new();
+        let mut batch_bytes = 0;
+        for item in items {
+            let size = serde_json::to_string(&item).map_or(usize::MAX, |json| json.len());
+            if !self.limits.allows(item.content.len())
+                || (self.limits.chunk_bytes > 0 && size > self.limits.chunk_bytes)
+            {
+                log::warn!("Not re-uploading history item {} of {} bytes, too large", item.id, item.content.len());
+                continue;
+            }
+            if self.limits.chunk_bytes > 0 && batch_bytes + size > self.limits.chunk_bytes {
+                messages.push((batch.len(), message(&batch)));
+                batch.clear();
+                batch_bytes = 0;
+            }
+            batch_bytes += size;
+            batch.push(item);
+        }
+        if !batch.is_empty() {
+            messages.push((batch.len(), message(&batch)));
+        }
+        messages
+    }
+
+    // Hands a remote item to the app unless it's over the size limit. The item still moves the
+    // history cursor, since the room stored it and a reconnect shouldn't fetch it again.
+    fn receive(&mut self, item: HistoryData, tx: &mpsc::UnboundedSender<WsEvent>) {
+        // Sensitive and targeted items are never stored, so the server can't resume from them
+        if !item.sensitive && item.target.is_none() {
+            self.cursor = Some(item.id.clone());
+        }
+
+        if !self.limits.allows(item.content.len()) {
+            log::warn!("Skipping incoming item of {} bytes, over the size limit", item.content.len());
+            let _ = tx.send(WsEvent::TooLarge {
+                size: item.content.len(),
+                limit: self.limits.max_bytes,
+                incoming: true,
+            });
+            return;
+        }
+        let _ = tx.send(WsEvent::ClipboardUpdate(item));
+    }
+
+    fn handle_message(&mut self, text: &str, tx: &mpsc::UnboundedSender<WsEvent>) -> Result<()> {
         let value: serde_json::Value = serde_json::from_str(text)
             .context("Failed to parse WebSocket message")?;
 
         match value.get("type").and_then(|v| v.as_str()) {
             Some("clipboard_update") => {
                 if let Some(data) = value.get("data") {
-                    if let Some(content) = data.get("content").and_then(|v| v.as_str()) {
-                        log::info!("Remote clipboard update: {}...", &content.chars().take(50).collect::<String>());
-                        let _ = tx.send(WsEvent::ClipboardUpdate(content.to_string()));
+                    if let Ok(item) = serde_json::from_value::<HistoryData>(data.clone()) {
+                        if item.origin.as_ref().is_some_and(|origin| self.sent_origins.contains(origin)) {
+                            log::debug!("Ignoring echo of our own clipboard update");
+                            return Ok(());
+                        }
+                        if item.sensitive {
+                            log::info!("Remote clipboard update: sensitive item");
+                        } else {
+                            log::info!("Remote clipboard update: {}...", &item.content.chars().take(50).collect::<String>());
+                        }
+                        self.receive(item, tx);
                     }
                 }
             }
+            Some("clipboard_chunk") => {
+                let Some(Ok(chunk)) = value.get("chunk").map(|chunk| serde_json::from_value::<Chunk>(chunk.clone())) else {
+                    return Ok(());
+                };
+                if !self.limits.allows(chunk.size) {
+                    // Announce the skipped item once, not for every chunk
+                    if chunk.index == 0 {
+                        log::warn!("Skipping incoming item of {} bytes, over the size limit", chunk.size);
+                        let _ = tx.send(WsEvent::TooLarge {
+                            size: chunk.size,
+                            limit: self.limits.max_bytes,
+                            incoming: true,
+                        });
+                    }
+                    return Ok(());
+                }
+
+                match self.reassembler.add(chunk) {
+                    Ok(Some(item)) => {
+                        log::info!("Remote clipboard update reassembled: {} bytes", item.content.len());
+                        self.receive(item, tx);
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        log::warn!("Dropping chunked clipboard update: {}", e);
+                        let _ = tx.send(WsEvent::Error(e.to_string()));
+                    }
+                }
+            }
+            Some("clipboard_history") if value.get("cleared").and_then(|v| v.as_bool()) == Some(true) => {
+                log::info!("History cleared on server");
+                self.cursor = None;
+                let include_pinned = value.get("include_pinned").and_then(|v| v.as_bool()) != Some(false);
+                let _ = tx.send(WsEvent::ClearHistory { include_pinned });
+            }
             Some("clipboard_history") => {
+                let answers_request = value.get("since").is_some()
+                    || value.get("resync").and_then(|v| v.as_bool()) == Some(true);
+                if self.awaiting_since && !answers_request {
+                    log::debug!("Ignoring full history pushed while waiting for incremental history");
+                    return Ok(());
+                }
+                if answers_request {
+                    self.awaiting_since = false;
+                }
+
                 if let Some(history) = value.get("history") {
                     if let Ok(history_items) = serde_json::from_value::<Vec<HistoryData>>(history.clone()) {
-                        log::info!("Received clipboard history: {} items", history_items.len());
-                        let _ = tx.send(WsEvent::ClipboardHistory(history_items));
+                        if let Some(newest) = history_items.iter().max_by_key(|item| item.timestamp) {
+                            self.cursor = Some(newest.id.clone());
+                        }
+
+                        if value.get("resync").and_then(|v| v.as_bool()) == Some(true) {
+                            log::info!("History cursor expired, full resync: {} items", history_items.len());
+                            let _ = tx.send(WsEvent::HistoryResync(history_items));
+                        } else {
+                            log::info!("Received clipboard history: {} items", history_items.len());
+                            let _ = tx.send(WsEvent::ClipboardHistory(history_items));
+                        }
                     }
                 }
             }
             Some("clear_history") => {
                 log::info!("History cleared on server");
-                let _ = tx.send(WsEvent::ClearHistory);
+                self.cursor = None;
+                let _ = tx.send(WsEvent::ClearHistory { include_pinned: true });
+            }
+            Some("pin") => {
+                if let Some(data) = value.get("data") {
+                    if let Ok(item) = serde_json::from_value::<HistoryData>(data.clone()) {
+                        log::info!("Item {} {} remotely", item.id, if item.pinned { "pinned" } else { "unpinned" });
+                        let _ = tx.send(WsEvent::Pin {
+                            id: item.id,
+                            content: item.content,
+                            pinned: item.pinned,
+                        });
+                    }
+                }
+            }
+            Some("delete_item") => {
+                if let Some(data) = value.get("data") {
+                    if let Ok(item) = serde_json::from_value::<HistoryData>(data.clone()) {
+                        log::info!("Item {} deleted remotely", item.id);
+                        let _ = tx.send(WsEvent::DeleteItem {
+                            id: item.id,
+                            content: item.content,
+                        });
+                    }
+                }
+            }
+            Some("presence") => {
+                if let Some(devices) = value.get("devices") {
+                    if let Ok(devices) = serde_json::from_value::<Vec<DeviceInfo>>(devices.clone()) {
+                        log::info!("Devices online: {}", devices.len());
+                        let _ = tx.send(WsEvent::Presence(devices));
+                    }
+                }
+            }
+            Some("capabilities") => {
+                let compression = value
+                    .get("compression")
+                    .and_then(|v| v.as_array())
+                    .is_some_and(|schemes| schemes.iter().any(|scheme| scheme == transfer::COMPRESSION));
+                if compression && self.limits.compress_bytes > 0 {
+                    log::info!("Server accepts compressed messages");
+                    self.compression = true;
+                }
+                if value.get("chunking").and_then(|v| v.as_bool()) == Some(true) && self.limits.chunk_bytes > 0 {
+                    log::info!("Server accepts chunked items");
+                    self.chunking = true;
+                }
             }
             Some("pong") => {
-                log::debug!("Received pong");
+                let seq = value.get("seq").and_then(|v| v.as_u64());
+                if let Some(latency) = self.heartbeat.pong(seq) {
+                    log::debug!("Received pong, latency {:?}", latency);
+                    let _ = tx.send(WsEvent::Latency(latency));
+                }
             }
             Some("error") => {
                 if let Some(error) = value.get("error").and_then(|v| v.as_str()) {
@@ -207,3 +685,106 @@ impl WebSocketClient {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(limits: TransferLimits) -> WebSocketClient {
+        let device = DeviceInfo {
+            id: "this-device".to_string(),
+            name: "Test".to_string(),
+        };
+        WebSocketClient::new("token".to_string(), "ws://localhost".to_string(), device, limits)
+    }
+
+    fn limits(max_bytes: usize, chunk_bytes: usize) -> TransferLimits {
+        TransferLimits {
+            max_bytes,
+            chunk_bytes,
+            compress_bytes: 0,
+        }
+    }
+
+    fn item(id: &str, content: &str) -> HistoryData {
+        HistoryData {
+            id: id.to_string(),
+            content: content.to_string(),
+            timestamp: 1000,
+            sensitive: false,
+            origin: None,
+            device: None,
+            target: None,
+            pinned: false,
+        }
+    }
+
+    #[test]
+    fn large_items_are_chunked_only_once_the_server_accepts_chunks() {
+        let mut client = client(limits(0, 100));
+        let content = "x".repeat(250);
+
+        let messages = client.update_messages(&content, None, "origin");
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("\"clipboard_update\""));
+
+        client.chunking = true;
+        let messages = client.update_messages(&content, None, "origin");
+        assert_eq!(messages.len(), 3);
+        assert!(messages.iter().all(|message| message.contains("\"clipboard_chunk\"")));
+    }
+
+    #[test]
+    fn restored_history_is_sent_in_batches_without_oversize_items() {
+        let client = client(limits(150, 200));
+        // About 90 bytes each once serialized, so two fit in a batch
+        let items = vec![
+            item("a", &"a".repeat(20)),
+            item("b", &"b".repeat(20)),
+            item("too-large", &"c".repeat(160)),
+            item("d", &"d".repeat(20)),
+            item("e", &"e".repeat(20)),
+            item("f", &"f".repeat(20)),
+        ];
+
+        let messages = client.restore_messages(items);
+
+        let counts: Vec<usize> = messages.iter().map(|(count, _)| *count).collect();
+        assert_eq!(counts, [2, 2, 1]);
+        assert!(messages.iter().all(|(_, message)| message.len() <= 200 + 50));
+        assert!(!messages.iter().any(|(_, message)| message.contains("too-large")));
+    }
+
+    #[test]
+    fn incoming_items_over_the_size_limit_are_dropped() {
+        let mut client = client(limits(10, 0));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let update = json!({ "type": "clipboard_update", "data": item("big", &"x".repeat(11)) });
+
+        client.handle_message(&update.to_string(), &tx).unwrap();
+
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(WsEvent::TooLarge { size: 11, limit: 10, incoming: true })
+        ));
+        assert!(rx.try_recv().is_err());
+        assert_eq!(client.cursor.as_deref(), Some("big"));
+    }
+
+    #[test]
+    fn reassembled_items_move_the_history_cursor() {
+        let mut sender = client(limits(0, 100));
+        sender.chunking = true;
+        let mut receiver = client(limits(0, 100));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        for message in sender.update_messages(&"x".repeat(250), None, "origin") {
+            receiver.handle_message(&message, &tx).unwrap();
+        }
+
+        let Ok(WsEvent::ClipboardUpdate(item)) = rx.try_recv() else {
+            panic!("no reassembled item");
+        };
+        assert_eq!(receiver.cursor, Some(item.id));
+    }
+}
@@ -1,4 +1,5 @@
 import { ClipboardItem, ClipboardRoom } from '../types';
+import { clearHistory, trimHistory } from './room-manager';
 
 export class ApiHandler {
   private room: ClipboardRoom;
@@ -27,7 +28,7 @@ export class ApiHandler {
       case 'POST':
         return this.handlePostClipboardUpdate(request);
       case 'DELETE':
-        return this.handleClearHistory();
+        return this.handleClearHistory(url.searchParams.get('include_pinned') === 'true');
       default:
         return new Response(JSON.stringify({ error: 'Method not allowed' }), { 
           status: 405,
@@ -62,9 +63,12 @@ export class ApiHandler {
         content: body.data.content as string,
         timestamp: Date.now()
       };
+      if (body.data.device?.id) {
+        item.device = { id: String(body.data.device.id), name: String(body.data.device.name ?? '') };
+      }
 
       this.room.history.unshift(item);
-      this.room.history = this.room.history.slice(0, 50);
+      this.room.history = trimHistory(this.room.history);
       this.room.lastActivity = Date.now();
 
       await this.state.storage.put(`room:${this.room.token}`, {
@@ -91,8 +95,9 @@ export class ApiHandler {
     }
   }
 
-  private async handleClearHistory(): Promise<Response> {
-    this.room.history = [];
+  // Pinned items survive a clear unless the caller asks for them to go too
+  private async handleClearHistory(includePinned: boolean): Promise<Response> {
+    this.room.history = clearHistory(this.room.history, includePinned);
     this.room.lastActivity = Date.now();
     
     await this.state.storage.put(`room:${this.room.token}`, {
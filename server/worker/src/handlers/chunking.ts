@@ -0,0 +1,80 @@
+import { ClipboardChunk } from '../types';
+
+// Largest item the server reassembles, the clients' default sync limit
+const MAX_TRANSFER_BYTES = 16 * 1024 * 1024;
+// How long an incomplete transfer waits for its remaining chunks
+const TRANSFER_TIMEOUT_MS = 60000;
+
+interface Transfer {
+  chunks: Map<number, string>;
+  total: number;
+  bytes: number;
+  started: number;
+}
+
+const encoder = new TextEncoder();
+
+// Collects the chunks of large items so they can be stored and sent whole to clients that
+// don't reassemble chunks themselves
+export class Reassembler {
+  private transfers = new Map<string, Transfer>();
+
+  // Adds a chunk from `senderId`, returning the content once every chunk has arrived.
+  // Throws if the chunks don't fit together or the content fails its size or checksum.
+  async add(senderId: string, chunk: ClipboardChunk): Promise<string | null> {
+    const now = Date.now();
+    // Senders that disconnected mid-transfer never finish
+    for (const [key, transfer] of this.transfers) {
+      if (now - transfer.started > TRANSFER_TIMEOUT_MS) {
+        this.transfers.delete(key);
+      }
+    }
+
+    if (chunk.index < 0 || chunk.index >= chunk.total || chunk.size > MAX_TRANSFER_BYTES) {
+      throw new Error(`Chunk ${chunk.index} of transfer ${chunk.transfer_id} is out of range`);
+    }
+
+    const key = `${senderId}:${chunk.transfer_id}`;
+    let transfer = this.transfers.get(key);
+    if (!transfer) {
+      transfer = { chunks: new Map(), total: chunk.total, bytes: 0, started: now };
+      this.transfers.set(key, transfer);
+    }
+    if (!transfer.chunks.has(chunk.index)) {
+      transfer.chunks.set(chunk.index, chunk.chunk);
+      transfer.bytes += encoder.encode(chunk.chunk).byteLength;
+    }
+    if (transfer.total !== chunk.total || transfer.bytes > chunk.size) {
+      this.transfers.delete(key);
+      throw new Error(`Chunks of transfer ${chunk.transfer_id} don't fit together`);
+    }
+    if (transfer.chunks.size < transfer.total) {
+      return null;
+    }
+
+    this.transfers.delete(key);
+    let content = '';
+    for (let index = 0; index < transfer.total; index++) {
+      content += transfer.chunks.get(index);
+    }
+    if (transfer.bytes !== chunk.size || await checksum(content) !== chunk.checksum) {
+      throw new Error(`Transfer ${chunk.transfer_id} failed its integrity check`);
+    }
+    return content;
+  }
+
+  // Drops the partial transfers of a client that disconnected
+  drop(senderId: string): void {
+    for (const key of this.transfers.keys()) {
+      if (key.startsWith(`${senderId}:`)) {
+        this.transfers.delete(key);
+      }
+    }
+  }
+}
+
+// SHA-256 of the content, hex encoded like the clients send it
+async function checksum(content: string): Promise<string> {
+  const digest = await crypto.subtle.digest('SHA-256', encoder.encode(content));
+  return [...new Uint8Array(digest)].map(byte => byte.toString(16).padStart(2, '0')).join('');
+}
@@ -0,0 +1,33 @@
+// Clients that offer compression get messages above this size deflated in binary frames
+export const COMPRESS_THRESHOLD = 1024;
+export const COMPRESSION = 'deflate';
+
+// Largest message a compressed frame may expand to
+const MAX_DECOMPRESSED_BYTES = 64 * 1024 * 1024;
+
+export async function compress(text: string): Promise<ArrayBuffer> {
+  const stream = new Blob([text]).stream().pipeThrough(new CompressionStream(COMPRESSION));
+  return new Response(stream).arrayBuffer();
+}
+
+export async function decompress(data: ArrayBuffer): Promise<string> {
+  const reader = new Blob([data]).stream().pipeThrough(new DecompressionStream(COMPRESSION)).getReader();
+  const decoder = new TextDecoder();
+  let text = '';
+  let size = 0;
+
+  for (;;) {
+    const { done, value } = await reader.read();
+    if (done) {
+      break;
+    }
+    size += value.byteLength;
+    if (size > MAX_DECOMPRESSED_BYTES) {
+      await reader.cancel();
+      throw new Error('Compressed message is too large');
+    }
+    text += decoder.decode(value, { stream: true });
+  }
+
+  return text + decoder.decode();
+}
@@ -56,3 +56,41 @@ export class RoomManager {
     }
   }
 }
+
+const HISTORY_LIMIT = 50;
+// Room history is stored as one value, which SQLite-backed Durable Objects cap at 2 MB, and
+// stored strings take up to two bytes per UTF-16 code unit
+export const HISTORY_LENGTH_LIMIT = 768 * 1024;
+
+// Pinned items don't count towards the item limit and are never trimmed, so they take their
+// share of the length budget first. Unpinned items are kept newest first while they fit; one
+// too large for what's left is dropped without pushing out the older ones.
+export function trimHistory(history: ClipboardItem[]): ClipboardItem[] {
+  let unpinned = 0;
+  let length = pinnedLength(history);
+  return history.filter(item => {
+    if (item.pinned) {
+      return true;
+    }
+    if (unpinned >= HISTORY_LIMIT || length + item.content.length > HISTORY_LENGTH_LIMIT) {
+      return false;
+    }
+    unpinned++;
+    length += item.content.length;
+    return true;
+  });
+}
+
+// Whether `item` fits in history next to the pinned items, i.e. whether it can be stored or pinned
+export function fitsHistory(history: ClipboardItem[], item: ClipboardItem): boolean {
+  const others = history.filter(existing => existing !== item);
+  return pinnedLength(others) + item.content.length <= HISTORY_LENGTH_LIMIT;
+}
+
+function pinnedLength(history: ClipboardItem[]): number {
+  return history.reduce((length, item) => item.pinned ? length + item.content.length : length, 0);
+}
+
+export function clearHistory(history: ClipboardItem[], includePinned: boolean): ClipboardItem[] {
+  return includePinned ? [] : history.filter(item => item.pinned);
+}
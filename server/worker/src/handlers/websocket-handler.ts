@@ -1,8 +1,20 @@
-import { ClipboardMessage, ClipboardItem, WebSocketConnection, ClipboardRoom } from '../types';
+import { ClipboardMessage, ClipboardItem, DeviceInfo, WebSocketConnection, ClipboardRoom } from '../types';
+import { clearHistory, fitsHistory, trimHistory } from './room-manager';
+import { COMPRESS_THRESHOLD, COMPRESSION, compress, decompress } from './compression';
+import { Reassembler } from './chunking';
+
+interface EncodedMessage {
+  text: string;
+  compressed: ArrayBuffer | null;
+}
+
+const everyone = (): boolean => true;
+const reassemblesChunks = (connection: WebSocketConnection): boolean => connection.chunking === true;
 
 export class WebSocketHandler {
   private room: ClipboardRoom;
   private state: DurableObjectState;
+  private reassembler = new Reassembler();
 
   constructor(room: ClipboardRoom, state: DurableObjectState) {
     this.room = room;
@@ -19,7 +31,9 @@ export class WebSocketHandler {
       const webSocketPair = new WebSocketPair();
       const [client, server] = Object.values(webSocketPair);
 
-      await this.handleSession(server, token);
+      // Clients that request history themselves ask not to be sent it on connect as well
+      const pushHistory = new URL(request.url).searchParams.get('history') !== 'request';
+      await this.handleSession(server, token, pushHistory);
 
       return new Response(null, {
         status: 101,
@@ -32,7 +46,7 @@ export class WebSocketHandler {
     }
   }
 
-  private async handleSession(websocket: WebSocket, token: string): Promise<void> {
+  private async handleSession(websocket: WebSocket, token: string, pushHistory: boolean): Promise<void> {
     try {
       websocket.accept();
       
@@ -54,29 +68,48 @@ export class WebSocketHandler {
         this.handleDisconnect(connectionId);
       });
 
-      await this.sendHistory(connectionId);
+      if (pushHistory) {
+        await this.sendHistory(connectionId);
+      }
     } catch (error) {
       console.error('WebSocket session error:', error);
       throw error;
     }
   }
 
-  private async handleMessage(connectionId: string, data: string): Promise<void> {
+  private async handleMessage(connectionId: string, data: string | ArrayBuffer): Promise<void> {
     try {
-      const message: ClipboardMessage = JSON.parse(data);
+      // Binary frames carry deflated JSON from clients that negotiated compression
+      const text = typeof data === 'string' ? data : await decompress(data);
+      const message: ClipboardMessage = JSON.parse(text);
       
       switch (message.type) {
+        case 'connect':
+          await this.handleConnect(connectionId, message);
+          break;
         case 'ping':
-          await this.handlePing(connectionId);
+          await this.handlePing(connectionId, message);
           break;
         case 'clipboard_update':
           await this.handleClipboardUpdate(connectionId, message);
           break;
+        case 'clipboard_chunk':
+          await this.handleClipboardChunk(connectionId, message);
+          break;
         case 'clipboard_history':
-          await this.sendHistory(connectionId);
+          await this.sendHistory(connectionId, message.since);
           break;
         case 'clear_history':
-          await this.handleClearHistory();
+          await this.handleClearHistory(message.include_pinned === true);
+          break;
+        case 'pin':
+          await this.handlePin(connectionId, message);
+          break;
+        case 'delete_item':
+          await this.handleDeleteItem(connectionId, message);
+          break;
+        case 'restore_history':
+          await this.handleRestoreHistory(message);
           break;
         default:
           await this.sendError(connectionId, `Unknown message type: ${message.type}`);
@@ -87,11 +120,62 @@ export class WebSocketHandler {
     }
   }
 
-  private async handlePing(connectionId: string): Promise<void> {
+  private async handleConnect(connectionId: string, message: ClipboardMessage): Promise<void> {
+    const connection = this.room.connections.get(connectionId);
+    if (!connection) {
+      return;
+    }
+
+    // Clients offer compression and chunking when connecting; older clients never see
+    // compressed frames or chunks
+    const capabilities: ClipboardMessage = {
+      type: 'capabilities',
+      token: this.room.token
+    };
+    if (message.compression?.includes(COMPRESSION)) {
+      connection.compression = true;
+      capabilities.compression = [COMPRESSION];
+    }
+    if (message.chunking === true) {
+      connection.chunking = true;
+      capabilities.chunking = true;
+    }
+    if (connection.compression || connection.chunking) {
+      connection.websocket.send(JSON.stringify(capabilities));
+    }
+
+    if (!message.device?.id) {
+      return;
+    }
+
+    connection.device = {
+      id: message.device.id,
+      name: message.device.name || 'Unknown device'
+    };
+    await this.broadcastPresence();
+  }
+
+  private async broadcastPresence(): Promise<void> {
+    const devices: DeviceInfo[] = [];
+    for (const connection of this.room.connections.values()) {
+      if (connection.device && !devices.some(device => device.id === connection.device!.id)) {
+        devices.push(connection.device);
+      }
+    }
+
+    await this.broadcastToAll({
+      type: 'presence',
+      token: this.room.token,
+      devices
+    });
+  }
+
+  // The sequence number is echoed so clients can match the pong to their ping and time it
+  private async handlePing(connectionId: string, message: ClipboardMessage): Promise<void> {
     const connection = this.room.connections.get(connectionId);
     if (connection) {
       connection.lastPing = Date.now();
-      connection.websocket.send(JSON.stringify({ type: 'pong' }));
+      connection.websocket.send(JSON.stringify({ type: 'pong', seq: message.seq }));
     }
   }
 
@@ -105,9 +189,46 @@ export class WebSocketHandler {
       content: message.data.content,
       timestamp: Date.now()
     };
+    if (message.data.sensitive) {
+      item.sensitive = true;
+    }
+    // Lets clients recognize their own updates and avoid re-broadcasting them
+    if (message.data.origin) {
+      item.origin = message.data.origin;
+    }
+    const device = this.room.connections.get(connectionId)?.device ?? message.data.device;
+    if (device) {
+      item.device = device;
+    }
 
-    this.room.history.unshift(item);
-    this.room.history = this.room.history.slice(0, 50);
+    await this.shareItem(connectionId, item, message.data.target);
+  }
+
+  // Stores an item in room history and sends it to the other clients that `accepts`
+  private async shareItem(
+    senderId: string,
+    item: ClipboardItem,
+    target: string | undefined,
+    accepts: (connection: WebSocketConnection) => boolean = everyone
+  ): Promise<void> {
+    // Targeted items go to one device only and are never stored in room history
+    if (target) {
+      await this.sendToDevice(senderId, target, {
+        type: 'clipboard_update',
+        token: this.room.token,
+        data: { ...item, target }
+      }, true, accepts);
+      return;
+    }
+
+    // Sensitive items are relayed but never persisted in room history, and items too large
+    // for the room's storage are relayed with a warning to the sender
+    if (!item.sensitive && !fitsHistory(this.room.history, item)) {
+      await this.sendError(senderId, `Item ${item.id} is too large to keep in room history, only connected devices received it`);
+    } else if (!item.sensitive) {
+      this.room.history.unshift(item);
+      this.room.history = trimHistory(this.room.history);
+    }
     this.room.lastActivity = Date.now();
 
     await this.state.storage.put(`room:${this.room.token}`, {
@@ -118,26 +239,192 @@ export class WebSocketHandler {
     });
 
     // Broadcast to other clients only (don't echo back to sender)
-    await this.broadcastToOthers(connectionId, {
+    await this.broadcastToOthers(senderId, {
       type: 'clipboard_update',
       token: this.room.token,
       data: item
-    });
+    }, accepts);
+  }
+
+  // Large items are relayed chunk by chunk to clients that reassemble them. The server
+  // reassembles them too, to store the item and send it whole to every other client.
+  private async handleClipboardChunk(connectionId: string, message: ClipboardMessage): Promise<void> {
+    if (!message.chunk) {
+      return;
+    }
+
+    const chunk = { ...message.chunk };
+    const device = this.room.connections.get(connectionId)?.device;
+    if (device) {
+      chunk.device = device;
+    }
+    this.room.lastActivity = Date.now();
+
+    const relay: ClipboardMessage = {
+      type: 'clipboard_chunk',
+      token: this.room.token,
+      chunk
+    };
+    if (chunk.target) {
+      // A missing device is reported once the whole item has arrived, not once per chunk
+      await this.sendToDevice(connectionId, chunk.target, relay, false, reassemblesChunks);
+    } else {
+      await this.broadcastToOthers(connectionId, relay, reassemblesChunks);
+    }
+
+    let content: string | null;
+    try {
+      content = await this.reassembler.add(connectionId, chunk);
+    } catch (error) {
+      await this.sendError(connectionId, error instanceof Error ? error.message : String(error));
+      return;
+    }
+    if (content === null) {
+      return;
+    }
+
+    // The transfer ID is the item's ID on clients that reassembled the chunks
+    const item: ClipboardItem = {
+      id: chunk.transfer_id,
+      content,
+      timestamp: Date.now()
+    };
+    if (chunk.sensitive) {
+      item.sensitive = true;
+    }
+    if (chunk.origin) {
+      item.origin = chunk.origin;
+    }
+    if (chunk.device) {
+      item.device = chunk.device;
+    }
+    await this.shareItem(connectionId, item, chunk.target, connection => !reassemblesChunks(connection));
   }
 
-  private async sendHistory(connectionId: string): Promise<void> {
+  // With a `since` cursor only newer items are sent; if the cursor is no longer in history
+  // the full history is sent with `resync` so the client can drop what it shouldn't keep
+  private async sendHistory(connectionId: string, since?: string): Promise<void> {
     const connection = this.room.connections.get(connectionId);
-    if (connection) {
-      connection.websocket.send(JSON.stringify({
-        type: 'clipboard_history',
+    if (!connection) {
+      return;
+    }
+
+    const message: ClipboardMessage = {
+      type: 'clipboard_history',
+      token: this.room.token,
+      history: this.room.history
+    };
+
+    if (since) {
+      const index = this.room.history.findIndex(item => item.id === since);
+      if (index >= 0) {
+        message.history = this.room.history.slice(0, index);
+        message.since = since;
+      } else {
+        message.resync = true;
+      }
+    }
+
+    this.deliver(connection, await this.encode(message));
+  }
+
+  private async handlePin(connectionId: string, message: ClipboardMessage): Promise<void> {
+    if (!message.data) {
+      return;
+    }
+
+    // Clients may only know an item by content if they copied it locally before it synced
+    const { id, content, pinned } = message.data;
+    const item = this.room.history.find(item => item.id === id || item.content === content);
+    if (!item) {
+      return;
+    }
+
+    // Pinned items are never trimmed, so they must leave room for history in storage
+    if (pinned && !item.pinned && !fitsHistory(this.room.history, item)) {
+      await this.sendError(connectionId, `Item ${item.id} can't be pinned, pinned items would exceed the room's storage`);
+      return;
+    }
+
+    if (pinned) {
+      item.pinned = true;
+    } else {
+      delete item.pinned;
+    }
+    this.room.history = trimHistory(this.room.history);
+    this.room.lastActivity = Date.now();
+
+    await this.state.storage.put(`room:${this.room.token}`, {
+      token: this.room.token,
+      history: this.room.history,
+      lastActivity: this.room.lastActivity
+    });
+
+    await this.broadcastToOthers(connectionId, {
+      type: 'pin',
+      token: this.room.token,
+      data: { id: item.id, content: item.content, timestamp: item.timestamp, pinned: pinned === true }
+    });
+  }
+
+  private async handleDeleteItem(connectionId: string, message: ClipboardMessage): Promise<void> {
+    if (!message.data) {
+      return;
+    }
+
+    const { id, content } = message.data;
+    const index = this.room.history.findIndex(item => item.id === id || item.content === content);
+    if (index >= 0) {
+      this.room.history.splice(index, 1);
+      this.room.lastActivity = Date.now();
+
+      await this.state.storage.put(`room:${this.room.token}`, {
         token: this.room.token,
-        history: this.room.history
-      }));
+        history: this.room.history,
+        lastActivity: this.room.lastActivity
+      });
+    }
+
+    // Relay even when the server never stored the item, other devices may still have it locally
+    await this.broadcastToOthers(connectionId, {
+      type: 'delete_item',
+      token: this.room.token,
+      data: { id, content, timestamp: Date.now() }
+    });
+  }
+
+  // Puts back items a client removed with a clear it then undid
+  private async handleRestoreHistory(message: ClipboardMessage): Promise<void> {
+    const restored = (message.history ?? []).filter(item =>
+      item.content && !item.sensitive &&
+      !this.room.history.some(existing => existing.id === item.id || existing.content === item.content)
+    );
+    if (restored.length === 0) {
+      return;
     }
+
+    this.room.history = trimHistory(
+      [...this.room.history, ...restored].sort((a, b) => b.timestamp - a.timestamp)
+    );
+    this.room.lastActivity = Date.now();
+
+    await this.state.storage.put(`room:${this.room.token}`, {
+      token: this.room.token,
+      history: this.room.history,
+      lastActivity: this.room.lastActivity
+    });
+
+    // The full history, since older clients replace theirs with whatever they receive
+    await this.broadcastToAll({
+      type: 'clipboard_history',
+      token: this.room.token,
+      history: this.room.history
+    });
   }
 
-  private async handleClearHistory(): Promise<void> {
-    this.room.history = [];
+  // Pinned items survive a clear unless the client asks for them to go too
+  private async handleClearHistory(includePinned: boolean): Promise<void> {
+    this.room.history = clearHistory(this.room.history, includePinned);
     this.room.lastActivity = Date.now();
     
     await this.state.storage.put(`room:${this.room.token}`, {
@@ -146,28 +433,51 @@ export class WebSocketHandler {
       lastActivity: this.room.lastActivity
     });
     
+    // Clients that merge history need to know this empty list is a clear, not just no news
     await this.broadcastToAll({
       type: 'clipboard_history',
       token: this.room.token,
-      history: []
+      history: this.room.history,
+      cleared: true,
+      include_pinned: includePinned
     });
   }
 
+  // Serializes once per message; the compressed form is only made if some client can use it
+  private async encode(message: ClipboardMessage): Promise<EncodedMessage> {
+    const text = JSON.stringify(message);
+    const anyCompression = [...this.room.connections.values()].some(connection => connection.compression);
+    return {
+      text,
+      compressed: anyCompression && text.length > COMPRESS_THRESHOLD ? await compress(text) : null
+    };
+  }
+
+  private deliver(connection: WebSocketConnection, encoded: EncodedMessage): void {
+    connection.websocket.send(connection.compression && encoded.compressed ? encoded.compressed : encoded.text);
+  }
+
   private async broadcastToAll(message: ClipboardMessage): Promise<void> {
+    const encoded = await this.encode(message);
     for (const [id, connection] of this.room.connections) {
       try {
-        connection.websocket.send(JSON.stringify(message));
+        this.deliver(connection, encoded);
       } catch (error) {
         this.room.connections.delete(id);
       }
     }
   }
 
-  private async broadcastToOthers(senderId: string, message: ClipboardMessage): Promise<void> {
+  private async broadcastToOthers(
+    senderId: string,
+    message: ClipboardMessage,
+    accepts: (connection: WebSocketConnection) => boolean = everyone
+  ): Promise<void> {
+    const encoded = await this.encode(message);
     for (const [id, connection] of this.room.connections) {
-      if (id !== senderId) {
+      if (id !== senderId && accepts(connection)) {
         try {
-          connection.websocket.send(JSON.stringify(message));
+          this.deliver(connection, encoded);
         } catch (error) {
           this.room.connections.delete(id);
         }
@@ -175,6 +485,36 @@ export class WebSocketHandler {
     }
   }
 
+  private async sendToDevice(
+    senderId: string,
+    deviceId: string,
+    message: ClipboardMessage,
+    reportMissing = true,
+    accepts: (connection: WebSocketConnection) => boolean = everyone
+  ): Promise<void> {
+    const encoded = await this.encode(message);
+    let delivered = false;
+    for (const [id, connection] of this.room.connections) {
+      if (id !== senderId && connection.device?.id === deviceId) {
+        // A device that takes the item another way still counts as reached
+        if (!accepts(connection)) {
+          delivered = true;
+          continue;
+        }
+        try {
+          this.deliver(connection, encoded);
+          delivered = true;
+        } catch (error) {
+          this.room.connections.delete(id);
+        }
+      }
+    }
+
+    if (!delivered && reportMissing) {
+      await this.sendError(senderId, `Device ${deviceId} is not connected`);
+    }
+  }
+
   private async sendError(connectionId: string, error: string): Promise<void> {
     const connection = this.room.connections.get(connectionId);
     if (connection) {
@@ -187,7 +527,12 @@ export class WebSocketHandler {
   }
 
   private handleDisconnect(connectionId: string): void {
+    const connection = this.room.connections.get(connectionId);
     this.room.connections.delete(connectionId);
+    this.reassembler.drop(connectionId);
+    if (connection?.device) {
+      this.broadcastPresence();
+    }
   }
 
   private extractToken(request: Request): string | null {
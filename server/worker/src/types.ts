@@ -1,19 +1,58 @@
+export interface DeviceInfo {
+  id: string;
+  name: string;
+}
+
 export interface ClipboardMessage {
-  type: 'connect' | 'disconnect' | 'clipboard_update' | 'clipboard_history' | 'clear_history' | 'ping' | 'pong' | 'error';
+  type: 'connect' | 'disconnect' | 'clipboard_update' | 'clipboard_chunk' | 'clipboard_history' | 'clear_history' | 'pin' | 'delete_item' | 'restore_history' | 'presence' | 'capabilities' | 'ping' | 'pong' | 'error';
   token: string;
   data?: {
     content: string;
     timestamp: number;
     id: string;
+    sensitive?: boolean;
+    origin?: string;
+    device?: DeviceInfo;
+    target?: string;
+    pinned?: boolean;
   };
+  chunk?: ClipboardChunk;
   history?: ClipboardItem[];
+  cleared?: boolean;
+  include_pinned?: boolean;
+  since?: string;
+  resync?: boolean;
+  device?: DeviceInfo;
+  devices?: DeviceInfo[];
+  compression?: string[];
+  chunking?: boolean;
+  seq?: number;
   error?: string;
 }
 
+// One piece of a large clipboard update; receivers reassemble and verify the content
+export interface ClipboardChunk {
+  transfer_id: string;
+  index: number;
+  total: number;
+  size: number;
+  checksum: string;
+  chunk: string;
+  timestamp: number;
+  sensitive?: boolean;
+  origin?: string;
+  device?: DeviceInfo;
+  target?: string;
+}
+
 export interface ClipboardItem {
   id: string;
   content: string;
   timestamp: number;
+  sensitive?: boolean;
+  origin?: string;
+  device?: DeviceInfo;
+  pinned?: boolean;
 }
 
 export interface WebSocketConnection {
@@ -21,6 +60,9 @@ export interface WebSocketConnection {
   websocket: WebSocket;
   token: string;
   lastPing: number;
+  device?: DeviceInfo;
+  compression?: boolean;
+  chunking?: boolean;
 }
 
 export interface ClipboardRoom {
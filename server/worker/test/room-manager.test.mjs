@@ -0,0 +1,49 @@
+import assert from 'node:assert/strict';
+import { test } from 'node:test';
+import { HISTORY_LENGTH_LIMIT, fitsHistory, trimHistory } from '../.test-build/handlers/room-manager.js';
+
+function item(id, length, pinned = false) {
+  return { id, content: 'x'.repeat(length), timestamp: 0, ...(pinned ? { pinned } : {}) };
+}
+
+const ids = history => history.map(item => item.id);
+
+test('an oversized item at the head is dropped without the older items', () => {
+  const history = [item('huge', HISTORY_LENGTH_LIMIT + 1), item('a', 10), item('b', 10)];
+
+  assert.deepEqual(ids(trimHistory(history)), ['a', 'b']);
+});
+
+test('newest items are kept while they fit the length budget', () => {
+  const half = HISTORY_LENGTH_LIMIT / 2;
+  const history = [item('a', half), item('b', half), item('c', 1)];
+
+  assert.deepEqual(ids(trimHistory(history)), ['a', 'b']);
+});
+
+test('pinned items are kept and count towards the length budget', () => {
+  const history = [item('new', 100), item('pinned', HISTORY_LENGTH_LIMIT - 50, true), item('old', 40)];
+
+  assert.deepEqual(ids(trimHistory(history)), ['pinned', 'old']);
+});
+
+test('at most 50 unpinned items are kept', () => {
+  const history = Array.from({ length: 60 }, (_, index) => item(`item-${index}`, 1));
+  history.push(item('pinned', 1, true));
+
+  const trimmed = trimHistory(history);
+
+  assert.equal(trimmed.length, 51);
+  assert.equal(trimmed.at(-2).id, 'item-49');
+  assert.equal(trimmed.at(-1).id, 'pinned');
+});
+
+test('items fit history only next to what pinned items take up', () => {
+  const pinned = item('pinned', HISTORY_LENGTH_LIMIT - 10, true);
+  const history = [pinned, item('a', 5)];
+
+  assert.ok(fitsHistory(history, item('small', 10)));
+  assert.ok(!fitsHistory(history, item('large', 11)));
+  assert.ok(fitsHistory(history, history[1]));
+  assert.ok(fitsHistory(history, pinned));
+});